@@ -0,0 +1,171 @@
+//! Compare the lock-free, channel and work-stealing schedulers on many tiny jobs, and on
+//! how long a job takes to submit and to start.
+//!
+//! Run with `cargo bench --bench scheduler`. Results are written to stderr.
+
+use std::{hint::black_box, sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc}, thread, time::{Duration, Instant}};
+
+use server_rs::{logging::{self, Level, Logger, StderrSink}, Scheduler, ThreadPool};
+
+/// The number of jobs submitted in each run.
+const JOBS: usize = 100_000;
+
+/// The number of jobs that each submit `JOBS / FAN_OUT` more jobs in the nested run.
+const FAN_OUT: usize = 100;
+
+/// The number of times each run is repeated. The fastest time is reported.
+const RUNS: usize = 5;
+
+/// The number of jobs submitted one at a time in the latency run.
+const LATENCY_JOBS: usize = 10_000;
+
+/// Every scheduler, in the order they are reported.
+const SCHEDULERS: [Scheduler; 3] = [Scheduler::LockFree, Scheduler::Channel, Scheduler::WorkStealing];
+
+fn main() {
+    // Every run starts and stops pools, which the workers and supervisor log at `Info`.
+    // Only let warnings through, so they don't bury the results.
+    logging::set_logger(Logger::new(Arc::new(StderrSink)).with_level(Some(Level::Warn)));
+
+    let threads = thread::available_parallelism().map_or(4, |threads| threads.get());
+    eprintln!("{JOBS} tiny jobs on {threads} threads, best of {RUNS} runs");
+
+    for scheduler in SCHEDULERS {
+        let external = best_of(|| submit_from_outside(scheduler, threads));
+        let concurrent = best_of(|| submit_from_threads(scheduler, threads));
+        let nested = best_of(|| submit_from_jobs(scheduler, threads));
+        eprintln!(
+            "{:<14} from outside: {:>10.2?} ({:>6.0} ns/job)   from {threads} threads: {:>10.2?} ({:>6.0} ns/job)   from jobs: {:>10.2?} ({:>6.0} ns/job)",
+            format!("{scheduler:?}"),
+            external,
+            external.as_nanos() as f64 / JOBS as f64,
+            concurrent,
+            concurrent.as_nanos() as f64 / JOBS as f64,
+            nested,
+            nested.as_nanos() as f64 / JOBS as f64,
+        );
+    }
+
+    eprintln!("\n{LATENCY_JOBS} jobs submitted one at a time to an idle pool, median / 99th percentile");
+    for scheduler in SCHEDULERS {
+        let (submit, dispatch) = latency(scheduler, threads);
+        eprintln!(
+            "{:<14} submit: {:>9.2?} / {:>9.2?}   dispatch: {:>9.2?} / {:>9.2?}",
+            format!("{scheduler:?}"),
+            percentile(&submit, 50),
+            percentile(&submit, 99),
+            percentile(&dispatch, 50),
+            percentile(&dispatch, 99),
+        );
+    }
+}
+
+/// Run `bench` `RUNS` times and return the fastest time.
+fn best_of(mut bench: impl FnMut() -> Duration) -> Duration {
+    (0..RUNS).map(|_| bench()).min().unwrap()
+}
+
+/// Time submitting `JOBS` jobs from the main thread until they have all run.
+fn submit_from_outside(scheduler: Scheduler, threads: usize) -> Duration {
+    let pool = ThreadPool::build_with_scheduler(threads, scheduler).unwrap();
+    let done = Arc::new(AtomicUsize::new(0));
+
+    let start = Instant::now();
+    for i in 0..JOBS {
+        let done = Arc::clone(&done);
+        let _ = pool.execute(move || {
+            black_box(i);
+            done.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    wait_for(&done, JOBS);
+    start.elapsed()
+}
+
+/// Time submitting `JOBS` jobs split between `threads` threads until they have all run, so
+/// that the submitters contend with each other as well as with the workers.
+fn submit_from_threads(scheduler: Scheduler, threads: usize) -> Duration {
+    let pool = Arc::new(ThreadPool::build_with_scheduler(threads, scheduler).unwrap());
+    let done = Arc::new(AtomicUsize::new(0));
+
+    let start = Instant::now();
+    let submitters: Vec<_> = (0..threads)
+        .map(|submitter| {
+            let (pool, done) = (Arc::clone(&pool), Arc::clone(&done));
+            thread::spawn(move || {
+                for i in (submitter..JOBS).step_by(threads) {
+                    let done = Arc::clone(&done);
+                    let _ = pool.execute(move || {
+                        black_box(i);
+                        done.fetch_add(1, Ordering::Relaxed);
+                    });
+                }
+            })
+        })
+        .collect();
+    for submitter in submitters {
+        submitter.join().unwrap();
+    }
+    wait_for(&done, JOBS);
+    start.elapsed()
+}
+
+/// Time `FAN_OUT` jobs that each submit their share of `JOBS` jobs, until they have all run.
+fn submit_from_jobs(scheduler: Scheduler, threads: usize) -> Duration {
+    let pool = Arc::new(ThreadPool::build_with_scheduler(threads, scheduler).unwrap());
+    let done = Arc::new(AtomicUsize::new(0));
+
+    let start = Instant::now();
+    for _ in 0..FAN_OUT {
+        let (inner, done) = (Arc::clone(&pool), Arc::clone(&done));
+        let _ = pool.execute(move || {
+            for i in 0..JOBS / FAN_OUT {
+                let done = Arc::clone(&done);
+                let _ = inner.execute(move || {
+                    black_box(i);
+                    done.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+        });
+    }
+    wait_for(&done, JOBS);
+    start.elapsed()
+}
+
+/// Spin until `done` reaches `count`.
+fn wait_for(done: &AtomicUsize, count: usize) {
+    while done.load(Ordering::Relaxed) < count {
+        thread::yield_now();
+    }
+}
+
+/// Submit `LATENCY_JOBS` jobs one at a time, each once the one before has started, and time
+/// how long each `execute` call took and how long after it was called the job started.
+/// 
+/// ## Returns
+/// The submit times and the dispatch times, each sorted.
+fn latency(scheduler: Scheduler, threads: usize) -> (Vec<Duration>, Vec<Duration>) {
+    let pool = ThreadPool::build_with_scheduler(threads, scheduler).unwrap();
+    let (sender, started) = mpsc::channel();
+    let mut submit = Vec::with_capacity(LATENCY_JOBS);
+    let mut dispatch = Vec::with_capacity(LATENCY_JOBS);
+
+    for _ in 0..LATENCY_JOBS {
+        let sender = sender.clone();
+        let called = Instant::now();
+        let _ = pool.execute(move || {
+            let _ = sender.send(Instant::now());
+        });
+        submit.push(called.elapsed());
+        dispatch.push(started.recv().unwrap().saturating_duration_since(called));
+    }
+
+    submit.sort_unstable();
+    dispatch.sort_unstable();
+    (submit, dispatch)
+}
+
+/// The `percent`th percentile of sorted times.
+fn percentile(times: &[Duration], percent: usize) -> Duration {
+    times[(times.len() - 1) * percent / 100]
+}
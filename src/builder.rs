@@ -0,0 +1,291 @@
+use std::{num::NonZero, sync::Arc, thread, time::Duration};
+
+use crate::{logging::Logger, priority::DEFAULT_AGING, queue::JobQueue, shared::Sizing, state::{StateInit, StatefulPool}, sync::Builder, PoolCreationError, RejectionPolicy, Scheduler, ThreadPool};
+
+/// The name given to worker threads when none is set, followed by the worker's id.
+const DEFAULT_THREAD_NAME: &str = "server-rs-worker";
+
+/// A callback run on a worker's thread, given the worker's id.
+type Hook = dyn Fn(usize) + Send + Sync;
+
+/// Callbacks run on each worker's thread at points in its life.
+/// 
+/// ## Fields
+/// - `on_thread_start`: Run when a worker's thread starts, before it takes any jobs.
+/// - `on_thread_stop`: Run when a worker's thread stops, after its last job.
+/// - `before_job`: Run before every job.
+/// - `after_job`: Run after every job, even one that panicked.
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    pub(crate) on_thread_start: Option<Arc<Hook>>,
+    pub(crate) on_thread_stop: Option<Arc<Hook>>,
+    pub(crate) before_job: Option<Arc<Hook>>,
+    pub(crate) after_job: Option<Arc<Hook>>,
+}
+
+impl Hooks {
+    /// Run a hook, if it has been set.
+    pub(crate) fn run(hook: &Option<Arc<Hook>>, id: usize) {
+        if let Some(hook) = hook {
+            hook(id);
+        }
+    }
+}
+
+/// How a pool's worker threads are spawned.
+/// 
+/// ## Fields
+/// - `name`: The prefix of each worker thread's name, which is followed by the worker's id.
+/// - `stack_size`: The stack size of each worker thread, if not the default.
+/// - `hooks`: Callbacks run on each worker's thread.
+/// - `replace_stuck_workers`: Whether to start a replacement for a worker whose job has
+///   overrun its deadline.
+#[derive(Clone)]
+pub(crate) struct ThreadConfig {
+    pub(crate) name: String,
+    pub(crate) stack_size: Option<usize>,
+    pub(crate) hooks: Hooks,
+    pub(crate) replace_stuck_workers: bool,
+}
+
+impl ThreadConfig {
+    /// A `thread::Builder` for the worker with the given id.
+    pub(crate) fn builder(&self, id: usize) -> Builder {
+        let builder = Builder::new().name(format!("{}-{}", self.name, id));
+        match self.stack_size {
+            Some(size) => builder.stack_size(size),
+            None => builder,
+        }
+    }
+}
+
+/// Configures and creates a `ThreadPool`.
+/// 
+/// Every setting has a default, so `ThreadPoolBuilder::new().build()` creates a fixed-size
+/// pool with a worker per CPU and an unbounded queue.
+/// 
+/// ## Fields
+/// - `num_threads`: The number of workers started up front and always kept.
+/// - `max_threads`: The most workers the pool grows to when jobs back up, if more than
+///   `num_threads`.
+/// - `keep_alive`: How long a worker above `num_threads` may sit idle before it retires.
+/// - `scheduler`: How jobs are handed to the workers.
+/// - `capacity`: The most jobs the queue holds, if it is bounded.
+/// - `policy`: What to do with a job submitted while a bounded queue is full.
+/// - `aging`: How long a job waits before it is treated as one priority level higher.
+/// - `threads`: How the worker threads are spawned.
+/// - `logger`: The logger to use instead of the global one, if any.
+#[derive(Clone)]
+pub struct ThreadPoolBuilder {
+    num_threads: usize,
+    max_threads: Option<usize>,
+    keep_alive: Option<Duration>,
+    scheduler: Scheduler,
+    capacity: Option<usize>,
+    policy: RejectionPolicy,
+    aging: Duration,
+    threads: ThreadConfig,
+    logger: Option<Arc<Logger>>,
+}
+
+impl Default for ThreadPoolBuilder {
+    fn default() -> ThreadPoolBuilder {
+        ThreadPoolBuilder::new()
+    }
+}
+
+impl ThreadPoolBuilder {
+    /// Create a ThreadPoolBuilder with the default settings.
+    pub fn new() -> ThreadPoolBuilder {
+        ThreadPoolBuilder {
+            num_threads: thread::available_parallelism().map_or(1, NonZero::get),
+            max_threads: None,
+            keep_alive: None,
+            scheduler: Scheduler::LockFree,
+            capacity: None,
+            policy: RejectionPolicy::Block,
+            aging: DEFAULT_AGING,
+            threads: ThreadConfig {
+                name: DEFAULT_THREAD_NAME.to_string(),
+                stack_size: None,
+                hooks: Hooks::default(),
+                replace_stuck_workers: false,
+            },
+            logger: None,
+        }
+    }
+
+    /// Set the number of workers started up front and always kept. Defaults to the number
+    /// of CPUs.
+    pub fn num_threads(mut self, num_threads: usize) -> ThreadPoolBuilder {
+        self.num_threads = num_threads;
+        self
+    }
+
+    /// Let the pool grow to `max_threads` workers when a job is submitted while every
+    /// worker is busy. Without a `keep_alive`, the workers it grows by are kept.
+    pub fn max_threads(mut self, max_threads: usize) -> ThreadPoolBuilder {
+        self.max_threads = Some(max_threads);
+        self
+    }
+
+    /// Retire workers above `num_threads` once they have been idle for `keep_alive`.
+    pub fn keep_alive(mut self, keep_alive: Duration) -> ThreadPoolBuilder {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Set how jobs are handed to the workers. Defaults to `Scheduler::LockFree`, which ages
+    /// a priority from when a job was last taken from it rather than from when each job was
+    /// queued, so a job may be promoted later than `aging` says. `Scheduler::Channel` ages
+    /// each job from when it was queued.
+    pub fn scheduler(mut self, scheduler: Scheduler) -> ThreadPoolBuilder {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Bound the queue to `capacity` jobs, with `policy` deciding what happens to a job
+    /// submitted while it is full. The `WorkStealing` scheduler doesn't support a bounded
+    /// queue.
+    pub fn bounded(mut self, capacity: usize, policy: RejectionPolicy) -> ThreadPoolBuilder {
+        self.capacity = Some(capacity);
+        self.policy = policy;
+        self
+    }
+
+    /// Set how long a job waits before it is treated as one priority level higher.
+    /// Defaults to half a second. See `scheduler` for how the default scheduler measures it.
+    pub fn aging(mut self, aging: Duration) -> ThreadPoolBuilder {
+        self.aging = aging;
+        self
+    }
+
+    /// Set the prefix of the worker threads' names, which is followed by the worker's id.
+    /// Defaults to `server-rs-worker`, so the fourth worker is `server-rs-worker-3`.
+    pub fn thread_name(mut self, name: impl Into<String>) -> ThreadPoolBuilder {
+        self.threads.name = name.into();
+        self
+    }
+
+    /// Set the stack size of the worker threads, in bytes. Defaults to the standard
+    /// library's default.
+    pub fn stack_size(mut self, stack_size: usize) -> ThreadPoolBuilder {
+        self.threads.stack_size = Some(stack_size);
+        self
+    }
+
+    /// Run `hook` on each worker's thread when it starts, before it takes any jobs. Use it to
+    /// set up thread-locals. Replacements for workers that died run it too.
+    /// 
+    /// A panicking hook takes its worker down, as a panicking job otherwise would.
+    pub fn on_thread_start<H>(mut self, hook: H) -> ThreadPoolBuilder
+    where
+        H: Fn(usize) + Send + Sync + 'static
+    {
+        self.threads.hooks.on_thread_start = Some(Arc::new(hook));
+        self
+    }
+
+    /// Run `hook` on each worker's thread when it shuts down or retires, after its last job.
+    pub fn on_thread_stop<H>(mut self, hook: H) -> ThreadPoolBuilder
+    where
+        H: Fn(usize) + Send + Sync + 'static
+    {
+        self.threads.hooks.on_thread_stop = Some(Arc::new(hook));
+        self
+    }
+
+    /// Run `hook` on the worker's thread before every job. If it panics, the job is dropped
+    /// without being run, and counted and reported as a job that panicked.
+    pub fn before_job<H>(mut self, hook: H) -> ThreadPoolBuilder
+    where
+        H: Fn(usize) + Send + Sync + 'static
+    {
+        self.threads.hooks.before_job = Some(Arc::new(hook));
+        self
+    }
+
+    /// Run `hook` on the worker's thread after every job, including jobs that panicked. If
+    /// it panics, the panic is reported, and a job that didn't panic is counted as one that did.
+    pub fn after_job<H>(mut self, hook: H) -> ThreadPoolBuilder
+    where
+        H: Fn(usize) + Send + Sync + 'static
+    {
+        self.threads.hooks.after_job = Some(Arc::new(hook));
+        self
+    }
+
+    /// Start a replacement for a worker as soon as its job overruns the deadline given to
+    /// `ThreadPool::execute_with_deadline`, so the pool keeps its capacity. The stuck worker
+    /// retires once its job finishes. Off by default, when overruns are only reported.
+    pub fn replace_stuck_workers(mut self, replace: bool) -> ThreadPoolBuilder {
+        self.threads.replace_stuck_workers = replace;
+        self
+    }
+
+    /// Log the pool's events to `logger` rather than the global logger set with
+    /// `logging::set_logger`.
+    pub fn logger(mut self, logger: Logger) -> ThreadPoolBuilder {
+        self.logger = Some(Arc::new(logger));
+        self
+    }
+
+    /// Create the ThreadPool.
+    /// 
+    /// ## Returns
+    /// The new `ThreadPool`, or a `PoolCreationError` if `num_threads` or the queue's
+    /// capacity is zero, `max_threads` is less than `num_threads`, a bounded queue was asked
+    /// of the `WorkStealing` scheduler, or a thread could not be spawned.
+    pub fn build(self) -> Result<ThreadPool, PoolCreationError> {
+        match self.capacity {
+            Some(0) => return Err(PoolCreationError::ZeroCapacity),
+            Some(_) if self.scheduler == Scheduler::WorkStealing => return Err(PoolCreationError::BoundedWorkStealing),
+            _ => {},
+        }
+
+        let sizing = Sizing {
+            min_threads: self.num_threads,
+            max_threads: self.max_threads.unwrap_or(self.num_threads),
+            keep_alive: self.keep_alive,
+        };
+        let queue = JobQueue::new(self.scheduler, self.capacity, self.aging);
+
+        ThreadPool::start(sizing, queue, self.capacity, self.policy, self.threads, self.logger)
+    }
+
+    /// Create a pool whose workers each own a state, which every job they run is given.
+    /// 
+    /// Each worker creates its state with `init` when it starts, after the `on_thread_start`
+    /// hook, including replacements for workers that died. A state is dropped on its worker's
+    /// thread when the worker exits, before the `on_thread_stop` hook, or straight away if a
+    /// job panics while using it.
+    /// 
+    /// ## Parameters
+    /// - `init`: Creates a worker's state, given the worker's id.
+    /// 
+    /// ## Returns
+    /// The new `StatefulPool`, or a `PoolCreationError` for the same reasons as `build`.
+    pub fn build_with_state<S, I>(mut self, init: I) -> Result<StatefulPool<S>, PoolCreationError>
+    where
+        S: 'static,
+        I: Fn(usize) -> S + Send + Sync + 'static
+    {
+        let init = Arc::new(StateInit::new(init));
+
+        let hook = self.threads.hooks.on_thread_start.take();
+        let start = Arc::clone(&init);
+        self.threads.hooks.on_thread_start = Some(Arc::new(move |id| {
+            Hooks::run(&hook, id);
+            start.start(id);
+        }));
+
+        let hook = self.threads.hooks.on_thread_stop.take();
+        let stop = Arc::clone(&init);
+        self.threads.hooks.on_thread_stop = Some(Arc::new(move |id| {
+            stop.stop();
+            Hooks::run(&hook, id);
+        }));
+
+        Ok(StatefulPool::new(self.build()?, init))
+    }
+}
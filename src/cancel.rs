@@ -0,0 +1,54 @@
+use std::sync::{atomic::Ordering, Arc};
+
+use crate::{queue::IntoJob, sync::AtomicBool, Job};
+
+/// A flag that tells jobs to stop, shared between whoever may cancel them and the jobs.
+/// 
+/// A job executed with `ThreadPool::execute_cancellable` is skipped if its token has been
+/// cancelled by the time a worker picks it up. A job that is already running isn't stopped,
+/// but can poll `is_cancelled` and return early. Clones share the same flag, so one token can
+/// cancel any number of jobs.
+/// 
+/// ## Fields
+/// - `cancelled`: Set once the token has been cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Create a token that hasn't been cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancel every job the token is attached to. This can't be undone.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+/// A job executed with `ThreadPool::execute_cancellable`, which is passed its token when it runs.
+/// 
+/// ## Fields
+/// - `f`: The job.
+/// - `token`: The job's token.
+pub(crate) struct Cancellable<F> {
+    pub(crate) f: F,
+    pub(crate) token: CancellationToken,
+}
+
+impl<F> IntoJob for Cancellable<F>
+where
+    F: FnOnce(&CancellationToken) + Send + 'static
+{
+    fn into_job(self) -> Job {
+        let Cancellable { f, token } = self;
+        Box::new(move || f(&token))
+    }
+}
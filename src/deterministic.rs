@@ -0,0 +1,149 @@
+use std::{panic, time::{Duration, Instant}};
+
+use crate::{executor::Executor, shared::lock, stats::Metrics, sync::Mutex, ExecuteError, Job, PoolStats, ShutdownReport};
+
+/// An `Executor` that runs every job on the calling thread, in an order that is the same
+/// from one run to the next, for tests.
+/// 
+/// An inline executor runs each job as soon as it is executed, so by the time `execute`
+/// returns the job has finished, and a panic in it is passed on to the caller. A seeded
+/// executor queues its jobs instead, and runs them when `run_until_idle` or `run_next` is
+/// called, picking each one from the queue with a random number generator seeded by the
+/// test. The same seed always gives the same order, so a test that fails for one ordering
+/// can be repeated with its seed, and a test can be run with many seeds to try many
+/// orderings.
+/// 
+/// Jobs may execute more jobs on the same executor, which run inline or join the queue.
+/// 
+/// ## Fields
+/// - `rng`: The state of the random number generator picking the next job, or `None` if
+///   jobs run inline.
+/// - `seed`: The seed the random number generator started from.
+/// - `queue`: The jobs waiting to run, with when they were executed.
+/// - `metrics`: Counts and timings of the jobs run.
+pub struct DeterministicExecutor {
+    rng: Option<Mutex<u64>>,
+    seed: Option<u64>,
+    queue: Mutex<Vec<(Job, Instant)>>,
+    metrics: Metrics,
+}
+
+impl DeterministicExecutor {
+    /// Create an executor that runs each job on the calling thread as soon as it is executed.
+    pub fn inline() -> DeterministicExecutor {
+        DeterministicExecutor {
+            rng: None,
+            seed: None,
+            queue: Mutex::new(Vec::new()),
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Create an executor that queues its jobs and runs them in an order picked by `seed`.
+    /// 
+    /// ## Parameters
+    /// - `seed`: Picks the order the jobs run in. Any value is fine.
+    pub fn seeded(seed: u64) -> DeterministicExecutor {
+        DeterministicExecutor {
+            rng: Some(Mutex::new(seed)),
+            seed: Some(seed),
+            queue: Mutex::new(Vec::new()),
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// The seed the executor was created with, or `None` if it runs jobs inline.
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// The number of jobs waiting to run.
+    pub fn pending(&self) -> usize {
+        lock(&self.queue).len()
+    }
+
+    /// Run one of the waiting jobs on the calling thread.
+    /// 
+    /// ## Returns
+    /// `true` if a job was run, `false` if none were waiting.
+    /// 
+    /// ## Panics
+    /// Passes on the job's panic. The jobs still waiting stay queued.
+    pub fn run_next(&self) -> bool {
+        let (job, queued_at) = {
+            let mut queue = lock(&self.queue);
+            if queue.is_empty() {
+                return false;
+            }
+            let index = match &self.rng {
+                Some(rng) => (next_random(&mut lock(rng)) % queue.len() as u64) as usize,
+                None => 0,
+            };
+            queue.swap_remove(index)
+        };
+
+        // The queue isn't locked while the job runs, so it may execute more jobs.
+        self.run(queued_at, job);
+        true
+    }
+
+    /// Run a job on the calling thread, counting it and passing its panic on.
+    fn run(&self, queued_at: Instant, f: impl FnOnce()) {
+        if let Err(payload) = self.metrics.run(queued_at, f) {
+            panic::resume_unwind(payload);
+        }
+    }
+
+    /// Run jobs on the calling thread until none are waiting, including any the jobs execute
+    /// as they run.
+    /// 
+    /// ## Returns
+    /// The number of jobs that were run.
+    /// 
+    /// ## Panics
+    /// Passes on the panic of the first job to panic. The jobs still waiting stay queued.
+    pub fn run_until_idle(&self) -> usize {
+        let mut ran = 0;
+        while self.run_next() {
+            ran += 1;
+        }
+        ran
+    }
+}
+
+impl Executor for DeterministicExecutor {
+    fn execute<F>(&self, f: F) -> Result<(), ExecuteError<F>>
+    where
+        F: FnOnce() + Send + 'static
+    {
+        if self.rng.is_some() {
+            lock(&self.queue).push((Box::new(f), Instant::now()));
+        } else {
+            self.run(Instant::now(), f);
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> PoolStats {
+        self.metrics.stats(self.pending(), 0, 0)
+    }
+
+    /// Run every waiting job, as `run_until_idle` does. They all run on the calling thread,
+    /// so `timeout` is ignored.
+    fn shutdown(self, _timeout: Duration) -> ShutdownReport {
+        self.run_until_idle();
+        ShutdownReport {
+            stats: self.stats(),
+            ..ShutdownReport::default()
+        }
+    }
+}
+
+/// Step a SplitMix64 generator, returning its next number.
+fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
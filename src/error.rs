@@ -0,0 +1,121 @@
+use std::{error::Error, fmt, io};
+
+/// An error returned when a `ThreadPool` could not be created.
+/// 
+/// ## Variants
+/// - `ZeroSize`: The pool was asked for zero threads.
+/// - `ZeroCapacity`: A bounded pool was asked for a queue that can't hold any jobs.
+/// - `MaxBelowMin`: An elastic pool was asked for fewer `max_threads` than `min_threads`.
+/// - `BoundedWorkStealing`: A pool using `Scheduler::WorkStealing` was asked for a bounded
+///   queue, which only `Scheduler::LockFree` and `Scheduler::Channel` support.
+/// - `Spawn`: The operating system refused to spawn a worker thread. `spawned`
+///   workers were running out of the `requested` total. When creating a pool, they have
+///   since been shut down. When growing one, with `ThreadPool::resize` or as jobs back up,
+///   they are kept, and the pool carries on with fewer workers than asked for.
+/// - `SupervisorSpawn`: The operating system refused to spawn the supervisor thread.
+#[derive(Debug)]
+pub enum PoolCreationError {
+    ZeroSize,
+    ZeroCapacity,
+    MaxBelowMin {
+        min_threads: usize,
+        max_threads: usize,
+    },
+    BoundedWorkStealing,
+    Spawn {
+        spawned: usize,
+        requested: usize,
+        source: io::Error,
+    },
+    SupervisorSpawn(io::Error),
+}
+
+impl fmt::Display for PoolCreationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolCreationError::ZeroSize => write!(f, "thread pool size must be greater than zero"),
+            PoolCreationError::ZeroCapacity => write!(f, "thread pool queue capacity must be greater than zero"),
+            PoolCreationError::MaxBelowMin { min_threads, max_threads } => write!(
+                f,
+                "thread pool max_threads ({max_threads}) must be at least min_threads ({min_threads})",
+            ),
+            PoolCreationError::BoundedWorkStealing => write!(f, "a work-stealing thread pool can't have a bounded queue"),
+            PoolCreationError::Spawn { spawned, requested, source } => write!(
+                f,
+                "failed to spawn worker thread {} of {requested}: {source}",
+                spawned + 1,
+            ),
+            PoolCreationError::SupervisorSpawn(source) => write!(f, "failed to spawn supervisor thread: {source}"),
+        }
+    }
+}
+
+impl Error for PoolCreationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PoolCreationError::ZeroSize
+            | PoolCreationError::ZeroCapacity
+            | PoolCreationError::MaxBelowMin { .. }
+            | PoolCreationError::BoundedWorkStealing => None,
+            PoolCreationError::Spawn { source, .. } | PoolCreationError::SupervisorSpawn(source) => Some(source),
+        }
+    }
+}
+
+/// An error returned when a job could not be handed to a `ThreadPool`.
+/// 
+/// The rejected job is handed back so the caller can run it elsewhere or clean up
+/// the resources it owns.
+/// 
+/// ## Variants
+/// - `Shutdown`: The pool has been shut down and no longer accepts jobs.
+/// - `NoWorkers`: Every worker thread in the pool has exited.
+/// - `QueueFull`: The pool's queue is at capacity and its `RejectionPolicy` is `Abort`.
+pub enum ExecuteError<F> {
+    Shutdown(F),
+    NoWorkers(F),
+    QueueFull(F),
+}
+
+impl<F> ExecuteError<F> {
+    /// Take back the job that was rejected.
+    /// 
+    /// ## Returns
+    /// The job that was passed to `ThreadPool::execute`.
+    pub fn into_inner(self) -> F {
+        match self {
+            ExecuteError::Shutdown(f) | ExecuteError::NoWorkers(f) | ExecuteError::QueueFull(f) => f,
+        }
+    }
+
+    /// Change the job held by the error, keeping the reason it was rejected.
+    pub(crate) fn map<G>(self, op: impl FnOnce(F) -> G) -> ExecuteError<G> {
+        match self {
+            ExecuteError::Shutdown(f) => ExecuteError::Shutdown(op(f)),
+            ExecuteError::NoWorkers(f) => ExecuteError::NoWorkers(op(f)),
+            ExecuteError::QueueFull(f) => ExecuteError::QueueFull(op(f)),
+        }
+    }
+}
+
+impl<F> fmt::Debug for ExecuteError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecuteError::Shutdown(_) => f.write_str("Shutdown(..)"),
+            ExecuteError::NoWorkers(_) => f.write_str("NoWorkers(..)"),
+            ExecuteError::QueueFull(_) => f.write_str("QueueFull(..)"),
+        }
+    }
+}
+
+impl<F> fmt::Display for ExecuteError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecuteError::Shutdown(_) => f.write_str("thread pool has been shut down"),
+            ExecuteError::NoWorkers(_) => f.write_str("thread pool has no live workers"),
+            ExecuteError::QueueFull(_) => f.write_str("thread pool queue is full"),
+        }
+    }
+}
+
+impl<F> Error for ExecuteError<F> {}
@@ -0,0 +1,255 @@
+use std::{collections::HashSet, sync::{atomic::Ordering, Arc, PoisonError}, thread, time::{Duration, Instant}};
+
+use crate::{shared::lock, stats::Metrics, sync::{AtomicUsize, Condvar, Mutex}, ExecuteError, PoolStats, ShutdownReport, ThreadPool};
+
+/// Something that runs jobs, such as a `ThreadPool`.
+/// 
+/// Code that only needs to hand jobs off can take an `Executor` rather than a `ThreadPool`,
+/// so the way jobs are run can be swapped: for a `ThreadPerJob` or an `InlineExecutor` to
+/// compare strategies under the same load, or for a `DeterministicExecutor` in tests to run
+/// the jobs in a repeatable order.
+pub trait Executor {
+    /// Execute a job.
+    /// 
+    /// ## Parameters
+    /// - `f`: The job to execute. This must implement `FnOnce()`.
+    /// 
+    /// ## Returns
+    /// `Ok(())` if the job was accepted, or an `ExecuteError` holding `f` if it wasn't.
+    fn execute<F>(&self, f: F) -> Result<(), ExecuteError<F>>
+    where
+        F: FnOnce() + Send + 'static;
+
+    /// Execute a job that is expected to finish within `deadline`. Executors that don't
+    /// watch for overrunning jobs execute it as any other.
+    /// 
+    /// ## Parameters
+    /// - `label`: A name for the job to report it by.
+    /// - `deadline`: How long the job may run once it has started.
+    /// - `f`: The job to execute. This must implement `FnOnce()`.
+    /// 
+    /// ## Returns
+    /// The same as `execute`.
+    fn execute_with_deadline<F>(&self, label: impl Into<String>, deadline: Duration, f: F) -> Result<(), ExecuteError<F>>
+    where
+        F: FnOnce() + Send + 'static
+    {
+        let _ = (label.into(), deadline);
+        self.execute(f)
+    }
+
+    /// Take a snapshot of what the executor is doing and has done, in the same form as
+    /// `ThreadPool::stats`. Figures an executor has no use for, such as `cancelled` for one
+    /// without a queue, stay at zero.
+    fn stats(&self) -> PoolStats;
+
+    /// Stop accepting jobs, and give the ones already executed until `timeout` to finish.
+    /// 
+    /// ## Parameters
+    /// - `timeout`: How long to wait for the jobs to finish.
+    /// 
+    /// ## Returns
+    /// A `ShutdownReport` of what was still running, or dropped, at the deadline, and the
+    /// executor's stats from after it shut down.
+    fn shutdown(self, timeout: Duration) -> ShutdownReport
+    where
+        Self: Sized;
+}
+
+impl Executor for ThreadPool {
+    fn execute<F>(&self, f: F) -> Result<(), ExecuteError<F>>
+    where
+        F: FnOnce() + Send + 'static
+    {
+        ThreadPool::execute(self, f)
+    }
+
+    fn execute_with_deadline<F>(&self, label: impl Into<String>, deadline: Duration, f: F) -> Result<(), ExecuteError<F>>
+    where
+        F: FnOnce() + Send + 'static
+    {
+        ThreadPool::execute_with_deadline(self, label, deadline, f)
+    }
+
+    fn stats(&self) -> PoolStats {
+        ThreadPool::stats(self)
+    }
+
+    fn shutdown(self, timeout: Duration) -> ShutdownReport {
+        ThreadPool::shutdown(self, timeout)
+    }
+}
+
+/// An `Executor` that spawns a new thread for every job, as a server handing each
+/// connection its own thread would.
+/// 
+/// There is no queue and no limit on the number of threads, so every job starts straight
+/// away, at the cost of spawning a thread for it. The time that takes is reported as the
+/// job's `queue_wait`.
+/// 
+/// ## Fields
+/// - `threads`: The bookkeeping shared with the job threads.
+pub struct ThreadPerJob {
+    threads: Arc<JobThreads>,
+}
+
+/// The bookkeeping shared between a `ThreadPerJob` and its threads.
+/// 
+/// ## Fields
+/// - `running`: The ids of the threads still running their job.
+/// - `done`: Signalled whenever a thread finishes its job.
+/// - `next_id`: The id to give the next thread.
+/// - `metrics`: Counts and timings of the jobs run.
+struct JobThreads {
+    running: Mutex<HashSet<usize>>,
+    done: Condvar,
+    next_id: AtomicUsize,
+    metrics: Metrics,
+}
+
+impl JobThreads {
+    /// Count a thread's job as finished.
+    fn finish(&self, id: usize) {
+        lock(&self.running).remove(&id);
+        self.done.notify_all();
+    }
+}
+
+impl ThreadPerJob {
+    /// Create a ThreadPerJob with no jobs running.
+    pub fn new() -> ThreadPerJob {
+        ThreadPerJob {
+            threads: Arc::new(JobThreads {
+                running: Mutex::new(HashSet::new()),
+                done: Condvar::new(),
+                next_id: AtomicUsize::new(0),
+                metrics: Metrics::new(),
+            }),
+        }
+    }
+}
+
+impl Default for ThreadPerJob {
+    fn default() -> ThreadPerJob {
+        ThreadPerJob::new()
+    }
+}
+
+impl Executor for ThreadPerJob {
+    /// Spawn a thread to run the job. If the operating system refuses to spawn one, the job
+    /// is handed back in `ExecuteError::NoWorkers`.
+    fn execute<F>(&self, f: F) -> Result<(), ExecuteError<F>>
+    where
+        F: FnOnce() + Send + 'static
+    {
+        let id = self.threads.next_id.fetch_add(1, Ordering::Relaxed);
+        lock(&self.threads.running).insert(id);
+
+        // `spawn` consumes the closure even when it fails, so the job is handed over through a
+        // slot it can be taken back out of.
+        let slot = Arc::new(Mutex::new(Some(f)));
+        let job = Arc::clone(&slot);
+        let threads = Arc::clone(&self.threads);
+        let queued_at = Instant::now();
+
+        let spawned = thread::Builder::new().name(format!("server-rs-job-{id}")).spawn(move || {
+            if let Some(f) = lock(&job).take() {
+                // The panic has been counted, and there is nobody else to pass it on to.
+                let _ = threads.metrics.run(queued_at, f);
+            }
+            threads.finish(id);
+        });
+
+        match spawned {
+            Ok(_) => Ok(()),
+            Err(_) => {
+                self.threads.finish(id);
+                self.threads.metrics.reject();
+                let f = lock(&slot).take().expect("the thread never started");
+                Err(ExecuteError::NoWorkers(f))
+            },
+        }
+    }
+
+    fn stats(&self) -> PoolStats {
+        let running = lock(&self.threads.running).len();
+        self.threads.metrics.stats(0, running, 0)
+    }
+
+    /// Wait until `timeout` for the threads to finish. Those that haven't are left to finish
+    /// on their own, and their ids are reported in `ShutdownReport::busy_workers`.
+    fn shutdown(self, timeout: Duration) -> ShutdownReport {
+        let deadline = Instant::now() + timeout;
+        let mut running = lock(&self.threads.running);
+
+        while !running.is_empty() {
+            let Some(timeout) = deadline.checked_duration_since(Instant::now()).filter(|timeout| !timeout.is_zero()) else { break };
+            running = self.threads.done.wait_timeout(running, timeout).unwrap_or_else(PoisonError::into_inner).0;
+        }
+
+        let mut busy_workers: Vec<usize> = running.iter().copied().collect();
+        busy_workers.sort_unstable();
+        drop(running);
+        ShutdownReport {
+            busy_workers,
+            discarded: 0,
+            stats: self.stats(),
+        }
+    }
+}
+
+/// An `Executor` that runs every job on the calling thread before `execute` returns.
+/// 
+/// Only one job runs at a time, with no threads to spawn or queue to pass through, which
+/// makes it the baseline to measure the others against. A panicking job is counted in
+/// `PoolStats::panicked` and otherwise ignored, as a `ThreadPool` would, so the caller
+/// carries on. For tests that want the panic, use `DeterministicExecutor::inline`.
+/// 
+/// ## Fields
+/// - `running`: The number of jobs running, which is more than one only if a job executed
+///   another.
+/// - `metrics`: Counts and timings of the jobs run.
+pub struct InlineExecutor {
+    running: AtomicUsize,
+    metrics: Metrics,
+}
+
+impl InlineExecutor {
+    /// Create an InlineExecutor.
+    pub fn new() -> InlineExecutor {
+        InlineExecutor {
+            running: AtomicUsize::new(0),
+            metrics: Metrics::new(),
+        }
+    }
+}
+
+impl Default for InlineExecutor {
+    fn default() -> InlineExecutor {
+        InlineExecutor::new()
+    }
+}
+
+impl Executor for InlineExecutor {
+    fn execute<F>(&self, f: F) -> Result<(), ExecuteError<F>>
+    where
+        F: FnOnce() + Send + 'static
+    {
+        self.running.fetch_add(1, Ordering::AcqRel);
+        let _ = self.metrics.run(Instant::now(), f);
+        self.running.fetch_sub(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    fn stats(&self) -> PoolStats {
+        self.metrics.stats(0, self.running.load(Ordering::Acquire), 0)
+    }
+
+    /// Every job has already run by the time `execute` returns, so there is nothing to wait for.
+    fn shutdown(self, _timeout: Duration) -> ShutdownReport {
+        ShutdownReport {
+            stats: self.stats(),
+            ..ShutdownReport::default()
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::{future::Future, panic::{self, AssertUnwindSafe}, pin::{pin, Pin}, sync::{atomic::Ordering, mpsc::Sender, Arc, Weak}, task::{Context, Poll, Wake, Waker}, thread::{self, Thread}};
+
+use crate::{shared::{lock, Shared}, sync::{AtomicBool, AtomicU8, Mutex}, worker::{self, HELP_POLL}, Job};
+
+/// The task is waiting to be woken.
+const IDLE: u8 = 0;
+/// The task is queued to be polled.
+const SCHEDULED: u8 = 1;
+/// The task is being polled.
+const RUNNING: u8 = 2;
+/// The task was woken while it was being polled, and must be polled again.
+const WOKEN: u8 = 3;
+/// The future has finished, or been dropped without finishing.
+const DONE: u8 = 4;
+
+/// A spawned future and the sender for its output, or its panic payload.
+type Running<F> = (Pin<Box<F>>, Sender<thread::Result<<F as Future>::Output>>);
+
+/// A future spawned with `ThreadPool::spawn_future`, polled on the pool's workers.
+/// 
+/// Its `Waker` queues the task on the pool again, unless it is already queued or finished,
+/// so a task is only ever queued once at a time. A task woken while it is being polled is
+/// queued again once the poll returns.
+/// 
+/// ## Fields
+/// - `future`: The future and the sender for its result, until it has finished.
+/// - `state`: Whether the task is idle, queued, being polled or finished.
+/// - `shared`: The pool the task runs on. Weak, so a waker kept after the pool has gone
+///   doesn't keep it alive.
+pub(crate) struct FutureTask<F: Future> {
+    future: Mutex<Option<Running<F>>>,
+    state: AtomicU8,
+    shared: Weak<Shared>,
+}
+
+impl<F> FutureTask<F>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    /// Create a task that is about to be queued for its first poll.
+    /// 
+    /// ## Parameters
+    /// - `future`: The future.
+    /// - `sender`: The channel the future's output, or its panic payload, is sent on.
+    /// - `shared`: The pool the task runs on.
+    pub(crate) fn new(future: F, sender: Sender<thread::Result<F::Output>>, shared: &Arc<Shared>) -> Arc<FutureTask<F>> {
+        Arc::new(FutureTask {
+            future: Mutex::new(Some((Box::pin(future), sender))),
+            state: AtomicU8::new(SCHEDULED),
+            shared: Arc::downgrade(shared),
+        })
+    }
+
+    /// The job that polls the task once.
+    pub(crate) fn job(self: Arc<Self>) -> Job {
+        let poll = PollJob { task: Some(self) };
+        Box::new(move || poll.run())
+    }
+
+    /// Queue the task to be polled. If the pool has gone, the future is abandoned, and its
+    /// handle sees a lost result.
+    fn schedule(self: Arc<Self>) {
+        match self.shared.upgrade() {
+            Some(shared) => shared.push_ready(self.job()),
+            None => self.abandon(),
+        }
+    }
+
+    /// Poll the future once, sending its output if it finished and queueing it again if it
+    /// was woken while it was polled.
+    fn poll(self: Arc<Self>) {
+        self.state.store(RUNNING, Ordering::Release);
+
+        let mut future = lock(&self.future);
+        let Some((pinned, _)) = future.as_mut() else { return };
+
+        let waker = Waker::from(Arc::clone(&self));
+        let mut cx = Context::from_waker(&waker);
+
+        // The task is only ever polled on one thread at a time, so the lock is never waited
+        // on. A panic is caught here so it doesn't poison it.
+        let outcome = match panic::catch_unwind(AssertUnwindSafe(|| pinned.as_mut().poll(&mut cx))) {
+            Ok(Poll::Pending) => {
+                drop(future);
+                if self.state.compare_exchange(RUNNING, IDLE, Ordering::AcqRel, Ordering::Acquire).is_err() {
+                    self.state.store(SCHEDULED, Ordering::Release);
+                    self.schedule();
+                }
+                return;
+            },
+            Ok(Poll::Ready(output)) => Ok(output),
+            Err(payload) => Err(payload),
+        };
+
+        self.state.store(DONE, Ordering::Release);
+        if let Some((pinned, sender)) = future.take() {
+            drop(pinned);
+            // Nobody may be waiting for the output any more, so a failed send is fine.
+            let _ = sender.send(outcome);
+        }
+    }
+
+    /// Drop the future without finishing it, which the handle sees as a lost result.
+    fn abandon(&self) {
+        self.state.store(DONE, Ordering::Release);
+        let abandoned = lock(&self.future).take();
+        drop(abandoned);
+    }
+}
+
+impl<F> Wake for FutureTask<F>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            let woken = match state {
+                IDLE => SCHEDULED,
+                RUNNING => WOKEN,
+                // Already queued, due another poll, or finished.
+                _ => return,
+            };
+
+            match self.state.compare_exchange_weak(state, woken, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => {
+                    if woken == SCHEDULED {
+                        Arc::clone(self).schedule();
+                    }
+                    return;
+                },
+                Err(actual) => state = actual,
+            }
+        }
+    }
+}
+
+/// A queued poll of a `FutureTask`.
+/// 
+/// If the pool drops the job without running it, because it was shutting down or discarded
+/// it, the future is dropped too. Otherwise a waker kept elsewhere would keep the task
+/// alive, and its handle waiting, with the task never queued again.
+/// 
+/// ## Fields
+/// - `task`: The task, until it has been polled.
+struct PollJob<F: Future + Send + 'static>
+where
+    F::Output: Send + 'static,
+{
+    task: Option<Arc<FutureTask<F>>>,
+}
+
+impl<F> PollJob<F>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    /// Poll the task.
+    fn run(mut self) {
+        if let Some(task) = self.task.take() {
+            task.poll();
+        }
+    }
+}
+
+impl<F> Drop for PollJob<F>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abandon();
+        }
+    }
+}
+
+/// Wakes a thread blocked in `block_on`.
+/// 
+/// ## Fields
+/// - `thread`: The blocked thread.
+/// - `woken`: Set when the future has been woken since it was last polled.
+struct ThreadWaker {
+    thread: Thread,
+    woken: AtomicBool,
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        self.thread.unpark();
+    }
+}
+
+/// Run a future to completion on the current thread, blocking until it finishes.
+/// 
+/// It is safe to call from a job running on a pool, including one polling a future spawned
+/// with `ThreadPool::spawn_future`. While the future is pending, the worker runs other queued
+/// jobs, so whatever the future waits on can't be stuck behind it.
+/// 
+/// ## Parameters
+/// - `future`: The future to run.
+/// 
+/// ## Returns
+/// The future's output.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let thread_waker = Arc::new(ThreadWaker {
+        thread: thread::current(),
+        woken: AtomicBool::new(false),
+    });
+    let waker = Waker::from(Arc::clone(&thread_waker));
+    let mut cx = Context::from_waker(&waker);
+    let helping = worker::is_worker();
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+
+        // A job run while helping may park and unpark this thread itself, so the flag, not
+        // the unpark, says whether the future was woken.
+        while !thread_waker.woken.swap(false, Ordering::AcqRel) {
+            if !helping {
+                thread::park();
+            } else if !worker::help() {
+                thread::park_timeout(HELP_POLL);
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::{sync::{Arc, PoisonError}, time::{Duration, Instant}};
+
+use crate::{queue::IntoJob, shared::lock, sync::{Condvar, Mutex}, worker::{self, HELP_POLL}, Job};
+
+/// A count of unfinished jobs that can be waited on, used by `JobGroup` and `Scope`.
+/// 
+/// ## Fields
+/// - `count`: The number of jobs that haven't finished, or been dropped, yet.
+/// - `done`: Signalled when `count` drops to zero.
+#[derive(Debug, Default)]
+pub(crate) struct Pending {
+    count: Mutex<usize>,
+    done: Condvar,
+}
+
+impl Pending {
+    /// Count another unfinished job.
+    pub(crate) fn add(&self) {
+        *lock(&self.count) += 1;
+    }
+
+    /// Count a job as finished, waking the waiters if it was the last.
+    pub(crate) fn finish(&self) {
+        let mut count = lock(&self.count);
+        *count -= 1;
+        if *count == 0 {
+            self.done.notify_all();
+        }
+    }
+
+    /// The number of unfinished jobs.
+    pub(crate) fn count(&self) -> usize {
+        *lock(&self.count)
+    }
+
+    /// Block until every job has finished.
+    /// 
+    /// If this is a pool's worker, the jobs may be queued behind it with no other worker free
+    /// to take them, so it runs queued jobs itself while it waits.
+    /// 
+    /// ## Parameters
+    /// - `deadline`: When to give up waiting, if ever.
+    /// 
+    /// ## Returns
+    /// `true` if every job finished, `false` if the deadline passed first.
+    pub(crate) fn wait(&self, deadline: Option<Instant>) -> bool {
+        let helping = worker::is_worker();
+        let mut count = lock(&self.count);
+
+        while *count > 0 {
+            let timeout = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(timeout) if !timeout.is_zero() => Some(timeout),
+                    _ => return false,
+                },
+                None => None,
+            };
+
+            if helping {
+                drop(count);
+                let ran = worker::help();
+                count = lock(&self.count);
+
+                // With nothing queued, the rest of the jobs are running elsewhere, though they
+                // may still queue more.
+                if !ran && *count > 0 {
+                    let poll = timeout.map_or(HELP_POLL, |timeout| timeout.min(HELP_POLL));
+                    count = self.done.wait_timeout(count, poll).unwrap_or_else(PoisonError::into_inner).0;
+                }
+            } else {
+                count = match timeout {
+                    Some(timeout) => self.done.wait_timeout(count, timeout).unwrap_or_else(PoisonError::into_inner).0,
+                    None => self.done.wait(count).unwrap_or_else(PoisonError::into_inner),
+                };
+            }
+        }
+
+        true
+    }
+}
+
+/// A set of jobs that can be waited on together, like a wait group.
+/// 
+/// Jobs join the group when they are executed with `ThreadPool::execute_in_group`, and
+/// leave it once they have finished, panicked, or been dropped without running. Clones
+/// share the same group, and a group may span several pools.
+/// 
+/// ## Fields
+/// - `pending`: The group's unfinished jobs.
+#[derive(Debug, Clone, Default)]
+pub struct JobGroup {
+    pending: Arc<Pending>,
+}
+
+impl JobGroup {
+    /// Create an empty group.
+    pub fn new() -> JobGroup {
+        JobGroup::default()
+    }
+
+    /// The number of jobs in the group that haven't finished yet.
+    pub fn pending(&self) -> usize {
+        self.pending.count()
+    }
+
+    /// Block until every job in the group has finished. Returns straight away if the group
+    /// is empty.
+    /// 
+    /// It is safe to call from a job running on a pool. While it waits, the worker runs
+    /// other queued jobs, so the group's jobs can't be stuck behind it.
+    pub fn wait(&self) {
+        self.pending.wait(None);
+    }
+
+    /// Block until every job in the group has finished or `timeout` has passed, whichever
+    /// comes first.
+    /// 
+    /// ## Parameters
+    /// - `timeout`: How long to wait.
+    /// 
+    /// ## Returns
+    /// `true` if every job finished, `false` if some were still unfinished at the timeout.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        self.pending.wait(Some(Instant::now() + timeout))
+    }
+}
+
+/// A job executed with `ThreadPool::execute_in_group`.
+/// 
+/// ## Fields
+/// - `f`: The job.
+/// - `group`: The group it joins once it is queued.
+pub(crate) struct Grouped<F> {
+    pub(crate) f: F,
+    pub(crate) group: JobGroup,
+}
+
+/// Keeps a job counted in its group, and takes it out again when dropped, whether or not the
+/// job ran.
+/// 
+/// ## Fields
+/// - `pending`: The group's unfinished jobs.
+struct Membership {
+    pending: Arc<Pending>,
+}
+
+impl Drop for Membership {
+    fn drop(&mut self) {
+        self.pending.finish();
+    }
+}
+
+impl<F> IntoJob for Grouped<F>
+where
+    F: FnOnce() + Send + 'static
+{
+    fn into_job(self) -> Job {
+        // Only join the group once the job is sure to be queued, so a rejected job leaves no trace.
+        let Grouped { f, group } = self;
+        group.pending.add();
+        let membership = Membership { pending: group.pending };
+
+        Box::new(move || {
+            let _membership = membership;
+            f();
+        })
+    }
+}
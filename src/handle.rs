@@ -0,0 +1,118 @@
+use std::{any::Any, error::Error, fmt, sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError}, thread, time::Duration};
+
+use crate::panic_message;
+
+/// A handle to a job submitted with `ThreadPool::spawn`, used to get its result back.
+/// 
+/// Dropping the handle detaches the job: it still runs, but its result is thrown away.
+/// 
+/// ## Fields
+/// - `receiver`: The channel the job's result, or its panic payload, is sent on.
+pub struct JobHandle<T> {
+    receiver: Receiver<thread::Result<T>>,
+}
+
+impl<T> JobHandle<T> {
+    /// Create a new JobHandle.
+    /// 
+    /// ## Parameters
+    /// - `receiver`: The channel the job will send its outcome on.
+    pub(crate) fn new(receiver: Receiver<thread::Result<T>>) -> JobHandle<T> {
+        JobHandle { receiver }
+    }
+
+    /// Block until the job finishes.
+    /// 
+    /// ## Returns
+    /// The job's return value, or a `JoinError` if it panicked or was dropped without running.
+    pub fn join(self) -> Result<T, JoinError> {
+        match self.receiver.recv() {
+            Ok(outcome) => outcome.map_err(JoinError::Panicked),
+            Err(_) => Err(JoinError::Lost),
+        }
+    }
+
+    /// Get the job's result if it has already finished, without blocking.
+    /// 
+    /// Once this has returned `Some`, the handle is spent and later calls return
+    /// `Some(Err(JoinError::Lost))`.
+    /// 
+    /// ## Returns
+    /// `None` if the job hasn't finished yet, otherwise the same as `join`.
+    pub fn try_join(&mut self) -> Option<Result<T, JoinError>> {
+        match self.receiver.try_recv() {
+            Ok(outcome) => Some(outcome.map_err(JoinError::Panicked)),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(JoinError::Lost)),
+        }
+    }
+
+    /// Block until the job finishes or `timeout` has passed, whichever comes first.
+    /// 
+    /// Once this has returned `Some`, the handle is spent and later calls return
+    /// `Some(Err(JoinError::Lost))`.
+    /// 
+    /// ## Parameters
+    /// - `timeout`: How long to wait for the job.
+    /// 
+    /// ## Returns
+    /// `None` if the job didn't finish in time, otherwise the same as `join`.
+    pub fn join_timeout(&mut self, timeout: Duration) -> Option<Result<T, JoinError>> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(outcome) => Some(outcome.map_err(JoinError::Panicked)),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(Err(JoinError::Lost)),
+        }
+    }
+}
+
+impl<T> fmt::Debug for JobHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobHandle").finish_non_exhaustive()
+    }
+}
+
+/// An error returned when a job's result can't be had from its `JobHandle`.
+/// 
+/// ## Variants
+/// - `Panicked`: The job panicked. Holds the panic payload.
+/// - `Lost`: The job was dropped without running, because the pool rejected or discarded
+///   it, or its result has already been taken from the handle.
+pub enum JoinError {
+    Panicked(Box<dyn Any + Send>),
+    Lost,
+}
+
+impl JoinError {
+    /// Get the panic payload out of the error.
+    /// 
+    /// ## Returns
+    /// The payload if the job panicked, so it can be inspected or passed to
+    /// `std::panic::resume_unwind`, otherwise `None`.
+    pub fn into_panic(self) -> Option<Box<dyn Any + Send>> {
+        match self {
+            JoinError::Panicked(payload) => Some(payload),
+            JoinError::Lost => None,
+        }
+    }
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Panicked(payload) => f.debug_tuple("Panicked").field(&panic_message(&**payload)).finish(),
+            JoinError::Lost => f.write_str("Lost"),
+        }
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Panicked(payload) => write!(f, "job panicked: {}", panic_message(&**payload)),
+            JoinError::Lost => f.write_str("job result is unavailable"),
+        }
+    }
+}
+
+impl Error for JoinError {}
@@ -0,0 +1,82 @@
+use std::{collections::{hash_map::{DefaultHasher, Entry}, HashMap, VecDeque}, hash::{Hash, Hasher}};
+
+use crate::{priority::Priority, queue::QueuedJob, shared::lock, sync::Mutex};
+
+/// Hash a key given to `ThreadPool::execute_keyed` down to the lane it runs in.
+/// 
+/// Two keys that hash alike share a lane, which only means their jobs run one at a time.
+pub(crate) fn lane<K: Hash + ?Sized>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The lanes of keyed jobs, which make sure no two jobs with the same key run at once.
+/// 
+/// A lane exists while one of its jobs is queued or running. Jobs submitted to a lane that
+/// exists wait in it, and are queued one at a time as the job ahead of them finishes.
+/// 
+/// ## Fields
+/// - `lanes`: The jobs waiting in each lane, by the lane's hash.
+pub(crate) struct KeyedLanes {
+    lanes: Mutex<HashMap<u64, VecDeque<(QueuedJob, Priority)>>>,
+}
+
+impl KeyedLanes {
+    /// Create a KeyedLanes with no busy lanes.
+    pub(crate) fn new() -> KeyedLanes {
+        KeyedLanes {
+            lanes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Claim a lane for a job, or have the job wait in it if it is already claimed.
+    /// 
+    /// ## Parameters
+    /// - `lane`: The lane's hash.
+    /// - `f`: The job.
+    /// - `priority`: How urgently the job should run once it is queued.
+    /// - `wait`: Wraps the job up to wait in the lane. Only called if the lane is busy, so
+    ///   that a job for a free lane is handed back as it was given.
+    /// 
+    /// ## Returns
+    /// The job back if the lane was free, in which case it is now claimed and the job should
+    /// be queued, or `None` if the job is waiting in the lane.
+    pub(crate) fn claim<F>(&self, lane: u64, f: F, priority: Priority, wait: impl FnOnce(F) -> QueuedJob) -> Option<F> {
+        match lock(&self.lanes).entry(lane) {
+            Entry::Occupied(mut waiting) => {
+                waiting.get_mut().push_back((wait(f), priority));
+                None
+            },
+            Entry::Vacant(free) => {
+                free.insert(VecDeque::new());
+                Some(f)
+            },
+        }
+    }
+
+    /// Pass a lane on once its job has finished, or has been dropped without running.
+    /// 
+    /// ## Parameters
+    /// - `lane`: The lane's hash.
+    /// 
+    /// ## Returns
+    /// The next job waiting in the lane, which should be queued. `None` if there isn't one,
+    /// in which case the lane is free again.
+    pub(crate) fn release(&self, lane: u64) -> Option<(QueuedJob, Priority)> {
+        let mut lanes = lock(&self.lanes);
+        let next = lanes.get_mut(&lane)?.pop_front();
+        if next.is_none() {
+            lanes.remove(&lane);
+        }
+        next
+    }
+
+    /// Take every job waiting in a lane and free every lane.
+    pub(crate) fn drain(&self) -> Vec<QueuedJob> {
+        lock(&self.lanes)
+            .drain()
+            .flat_map(|(_, waiting)| waiting.into_iter().map(|(job, _)| job))
+            .collect()
+    }
+}
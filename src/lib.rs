@@ -1,17 +1,94 @@
-use std::{sync::{mpsc::{self, Receiver, Sender}, Arc, Mutex}, thread::{self, JoinHandle}};
+use std::{any::Any, future::Future, hash::Hash, panic::{self, AssertUnwindSafe}, sync::{atomic::Ordering, mpsc, Arc}, time::{Duration, Instant}};
+
+mod builder;
+mod cancel;
+mod deterministic;
+mod error;
+mod executor;
+mod future;
+mod group;
+mod handle;
+mod keyed;
+mod lockfree;
+pub mod logging;
+#[cfg(all(test, loom))]
+mod model;
+mod mpmc;
+mod priority;
+mod queue;
+mod scope;
+mod shared;
+mod shutdown;
+mod state;
+mod stats;
+mod steal;
+mod sync;
+mod timer;
+mod watchdog;
+mod worker;
+
+pub use builder::ThreadPoolBuilder;
+pub use cancel::CancellationToken;
+pub use deterministic::DeterministicExecutor;
+pub use error::{ExecuteError, PoolCreationError};
+pub use executor::{Executor, InlineExecutor, ThreadPerJob};
+pub use future::block_on;
+pub use group::JobGroup;
+pub use handle::{JobHandle, JoinError};
+pub use priority::{Priority, QueueDepths};
+pub use queue::{RejectionPolicy, Scheduler};
+pub use scope::Scope;
+pub use shutdown::ShutdownReport;
+pub use state::StatefulPool;
+pub use stats::{Histogram, PoolStats};
+pub use timer::ScheduledJob;
+pub use watchdog::OverdueJob;
+
+use builder::ThreadConfig;
+use cancel::Cancellable;
+use future::FutureTask;
+use group::Grouped;
+use logging::Logger;
+use queue::JobQueue;
+use shared::{lock, Shared, Sizing};
+use sync::JoinHandle;
+use timer::{Task, Timer};
+use watchdog::Deadline;
+use worker::SupervisorEvent;
 
 /// A thread pool that can execute jobs.
 /// 
 /// ## Fields
-/// - `workers`: The workers in the pool.
-/// - `sender`: The sender end of the channel. Used to send work to the workers.
+/// - `shared`: The state shared between the pool and its workers, including the job queue.
+/// - `supervisor`: The thread that replaces workers which die unexpectedly.
 pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: Option<Sender<Job>>,
+    shared: Arc<Shared>,
+    supervisor: Option<JoinHandle<()>>,
 }
 
 /// A job that can be executed by a worker.
-type Job = Box<dyn FnOnce() + Send + 'static>;
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A callback told about jobs that panic, with the id of the worker that ran the job and
+/// the panic payload.
+type PanicHandler = dyn Fn(usize, &(dyn Any + Send)) + Send + Sync;
+
+/// Get the message out of a panic payload.
+/// 
+/// ## Parameters
+/// - `payload`: The payload of a panic, as passed to a panic handler.
+/// 
+/// ## Returns
+/// The panic message if the panic was raised with a string, otherwise a placeholder.
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
 
 impl ThreadPool {
     /// Create a new ThreadPool.
@@ -21,107 +98,724 @@ impl ThreadPool {
     /// 
     /// ## Returns
     /// A `ThreadPool` with `size` number of threads.
+    /// 
+    /// ## Panics
+    /// Panics if `size` is zero or if a worker thread could not be spawned.
+    /// Use [`ThreadPool::build`] to handle these cases instead.
     pub fn new(size: usize) -> ThreadPool {
-        assert!(size > 0);
+        match ThreadPool::build(size) {
+            Ok(pool) => pool,
+            Err(err) => panic!("failed to create thread pool: {err}"),
+        }
+    }
 
-        // Create a channel with a capacity of `size`.
-        let (sender, receiver) = mpsc::channel();
-        
-        // Wrap the receiver in an `Arc` and a `Mutex` to make it thread safe.
-        let receiver = Arc::new(Mutex::new(receiver));
+    /// Create a new ThreadPool, reporting failures instead of panicking.
+    /// 
+    /// ## Parameters
+    /// - `size`: The number of threads in the pool.
+    /// 
+    /// ## Returns
+    /// A `ThreadPool` with `size` number of threads, or a `PoolCreationError`
+    /// if `size` is zero or a worker thread could not be spawned. Any workers
+    /// spawned before the failure are shut down before the error is returned.
+    pub fn build(size: usize) -> Result<ThreadPool, PoolCreationError> {
+        ThreadPool::builder().num_threads(size).build()
+    }
+
+    /// Create a `ThreadPoolBuilder` to configure a new ThreadPool.
+    pub fn builder() -> ThreadPoolBuilder {
+        ThreadPoolBuilder::new()
+    }
+
+    /// Create a new pool whose workers each own a state of type `S`, which every job they run
+    /// is given as `&mut S`. Use it for resources that are expensive to set up for every job,
+    /// such as buffers, caches of open files or connections.
+    /// 
+    /// ## Parameters
+    /// - `size`: The number of threads in the pool.
+    /// - `init`: Creates a worker's state when the worker starts, given the worker's id.
+    /// 
+    /// ## Returns
+    /// A `StatefulPool` with `size` number of threads, or a `PoolCreationError`
+    /// if `size` is zero or a worker thread could not be spawned.
+    pub fn with_state<S, I>(size: usize, init: I) -> Result<StatefulPool<S>, PoolCreationError>
+    where
+        S: 'static,
+        I: Fn(usize) -> S + Send + Sync + 'static
+    {
+        ThreadPool::builder().num_threads(size).build_with_state(init)
+    }
 
-        // Create a vector to hold the threads.
-        let mut workers = Vec::with_capacity(size);
+    /// Create a new ThreadPool that hands jobs to its workers with the given `Scheduler`.
+    /// 
+    /// ## Parameters
+    /// - `size`: The number of threads in the pool.
+    /// - `scheduler`: How jobs are handed to the workers.
+    /// 
+    /// ## Returns
+    /// A `ThreadPool` with `size` number of threads, or a `PoolCreationError`
+    /// if `size` is zero or a worker thread could not be spawned.
+    pub fn build_with_scheduler(size: usize, scheduler: Scheduler) -> Result<ThreadPool, PoolCreationError> {
+        ThreadPool::builder().num_threads(size).scheduler(scheduler).build()
+    }
 
-        // Create `size` threads and store them in the vector.
-        for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+    /// Create a new ThreadPool that grows and shrinks with demand.
+    /// 
+    /// The pool starts `min_threads` workers. When a job is submitted while every worker is
+    /// busy, another worker is started, up to `max_threads`. Workers above `min_threads`
+    /// retire once they have been idle for `keep_alive`.
+    /// 
+    /// ## Parameters
+    /// - `min_threads`: The number of workers the pool always keeps.
+    /// - `max_threads`: The most workers the pool will run at once.
+    /// - `keep_alive`: How long a worker above `min_threads` may sit idle before it retires.
+    /// 
+    /// ## Returns
+    /// A `ThreadPool` with `min_threads` number of threads, or a `PoolCreationError` if
+    /// `min_threads` is zero, `max_threads` is less than `min_threads`, or a worker thread
+    /// could not be spawned.
+    pub fn build_elastic(min_threads: usize, max_threads: usize, keep_alive: Duration) -> Result<ThreadPool, PoolCreationError> {
+        ThreadPool::builder()
+            .num_threads(min_threads)
+            .max_threads(max_threads)
+            .keep_alive(keep_alive)
+            .build()
+    }
+
+    /// Create a new ThreadPool whose queue holds at most `capacity` jobs.
+    /// 
+    /// ## Parameters
+    /// - `size`: The number of threads in the pool.
+    /// - `capacity`: The number of jobs that can wait in the queue while every worker is busy.
+    /// - `policy`: What to do with a job submitted while the queue is full.
+    /// 
+    /// ## Returns
+    /// A `ThreadPool` with `size` number of threads, or a `PoolCreationError`
+    /// if `size` or `capacity` is zero or a worker thread could not be spawned.
+    pub fn build_bounded(size: usize, capacity: usize, policy: RejectionPolicy) -> Result<ThreadPool, PoolCreationError> {
+        ThreadPool::builder().num_threads(size).bounded(capacity, policy).build()
+    }
+
+    /// Spawn the workers for a new ThreadPool.
+    /// 
+    /// ## Parameters
+    /// - `sizing`: How many workers the pool runs.
+    /// - `queue`: The job queue.
+    /// - `capacity`: The most jobs the queue holds, if it is bounded.
+    /// - `policy`: What to do with a job submitted while a bounded queue is full.
+    /// - `threads`: How worker threads are spawned, and the hooks they run.
+    /// - `logger`: The logger to use instead of the global one, if any.
+    /// 
+    /// ## Returns
+    /// The new `ThreadPool`, or a `PoolCreationError` if it could not be created.
+    pub(crate) fn start(
+        sizing: Sizing,
+        queue: JobQueue,
+        capacity: Option<usize>,
+        policy: RejectionPolicy,
+        threads: ThreadConfig,
+        logger: Option<Arc<Logger>>,
+    ) -> Result<ThreadPool, PoolCreationError> {
+        if sizing.min_threads == 0 {
+            return Err(PoolCreationError::ZeroSize);
         }
+        if sizing.max_threads < sizing.min_threads {
+            return Err(PoolCreationError::MaxBelowMin {
+                min_threads: sizing.min_threads,
+                max_threads: sizing.max_threads,
+            });
+        }
+
+        // Create a channel for workers to report their deaths to the supervisor.
+        let (events, supervisor_events) = sync::mpsc::channel();
+
+        // Share the queue and the pool's bookkeeping with the workers.
+        let shared = Arc::new(Shared::new(sizing, queue, capacity, policy, events, threads, logger));
+
+        // Start the supervisor before any workers, so there's someone to replace them.
+        let supervisor = worker::spawn_supervisor(Arc::clone(&shared), supervisor_events)
+            .map_err(PoolCreationError::SupervisorSpawn)?;
+
+        // Create the pool up front so that, if spawning fails part way, dropping
+        // it shuts down the workers that were already created.
+        let pool = ThreadPool {
+            shared,
+            supervisor: Some(supervisor),
+        };
+
+        // Create `min_threads` threads and store them in the pool.
+        pool.shared.spawn_workers(sizing.min_threads)?;
 
         // Return the ThreadPool.
-        ThreadPool { 
-            workers, 
-            sender: Some(sender) 
-        }
+        Ok(pool)
     }
 
-    /// Execute a job on the ThreadPool.
+    /// Execute a job on the ThreadPool at `Priority::Normal`.
+    /// 
+    /// On a bounded pool whose queue is full, the pool's `RejectionPolicy` decides what
+    /// happens: with `Block` this call waits for space, with `CallerRuns` the job runs
+    /// on the calling thread before this call returns.
     /// 
     /// ## Parameters
     /// - `f`: The job to execute. This must implement `FnOnce()`.
-    pub fn execute<F>(&self, f: F)
+    /// 
+    /// ## Returns
+    /// `Ok(())` if the job was queued (or run), or an `ExecuteError` holding `f` if the pool
+    /// has shut down, no longer has any live workers to run it, or its queue is full.
+    pub fn execute<F>(&self, f: F) -> Result<(), ExecuteError<F>>
     where
         F: FnOnce() + Send + 'static
     {
-        let job = Box::new(f);
-        self.sender.as_ref().unwrap().send(job).unwrap();
+        self.execute_with_priority(Priority::Normal, f)
     }
-}
 
-/// Implement the `Drop` trait for `ThreadPool`.
-impl Drop for ThreadPool {
-    /// Shut down the ThreadPool.
-    fn drop(&mut self) {
-        // Drop the sender to signal to the workers that they should shut down.
-        drop(self.sender.take());
-        
-        // Loop through each worker and shut them down.
-        for worker in &mut self.workers {
-            println!("Shutting down worker {}", worker.id);
+    /// Execute a job on the ThreadPool ahead of, or behind, jobs of other priorities.
+    /// 
+    /// Waiting jobs run highest priority first, and in the order they were submitted within
+    /// a priority. To keep lower-priority jobs from being starved, a job is treated as one
+    /// level higher for every half second it has waited.
+    /// 
+    /// ## Parameters
+    /// - `priority`: How urgently the job should run.
+    /// - `f`: The job to execute. This must implement `FnOnce()`.
+    /// 
+    /// ## Returns
+    /// The same as `execute`.
+    pub fn execute_with_priority<F>(&self, priority: Priority, f: F) -> Result<(), ExecuteError<F>>
+    where
+        F: FnOnce() + Send + 'static
+    {
+        self.shared.submit(priority, f, None, None, None, self.shared.policy)
+    }
 
-            // Send a message to each worker to tell them to shut down.
-            if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
-            }
+    /// Execute a job on the ThreadPool that can be called off with a `CancellationToken`.
+    /// 
+    /// If `token` is cancelled before a worker picks the job up, the job is skipped. Once it
+    /// is running, the job is passed the token to check for itself.
+    /// 
+    /// ## Parameters
+    /// - `token`: The token that cancels the job.
+    /// - `f`: The job to execute. This must implement `FnOnce(&CancellationToken)`.
+    /// 
+    /// ## Returns
+    /// The same as `execute`.
+    pub fn execute_cancellable<F>(&self, token: &CancellationToken, f: F) -> Result<(), ExecuteError<F>>
+    where
+        F: FnOnce(&CancellationToken) + Send + 'static
+    {
+        let job = Cancellable { f, token: token.clone() };
+        self.shared
+            .submit(Priority::Normal, job, Some(token.clone()), None, None, self.shared.policy)
+            .map_err(|err| err.map(|job| job.f))
+    }
+
+    /// Execute a job on the ThreadPool that is expected to finish within `deadline`.
+    /// 
+    /// The job isn't stopped if it overruns, since a thread can't be stopped from outside,
+    /// but the pool's supervisor logs a warning with the worker's id, the job's label and how
+    /// long it has been running. If the pool was built with
+    /// `ThreadPoolBuilder::replace_stuck_workers`, a replacement worker is started as well.
+    /// 
+    /// ## Parameters
+    /// - `label`: A name for the job to report it by.
+    /// - `deadline`: How long the job may run, from when a worker picks it up.
+    /// - `f`: The job to execute. This must implement `FnOnce()`.
+    /// 
+    /// ## Returns
+    /// The same as `execute`.
+    pub fn execute_with_deadline<F>(&self, label: impl Into<String>, deadline: Duration, f: F) -> Result<(), ExecuteError<F>>
+    where
+        F: FnOnce() + Send + 'static
+    {
+        let deadline = Deadline {
+            label: label.into(),
+            budget: deadline,
+        };
+        self.shared.submit(Priority::Normal, f, None, Some(deadline), None, self.shared.policy)
+    }
+
+    /// The jobs executed with `execute_with_deadline` that are running past their deadline.
+    /// 
+    /// ## Returns
+    /// A snapshot of the overdue jobs, in no particular order.
+    pub fn overdue_jobs(&self) -> Vec<OverdueJob> {
+        self.shared.watchdog.overdue()
+    }
+
+    /// Execute a job on the ThreadPool that never runs at the same time as another job with
+    /// the same key, and runs after every job with that key executed before it.
+    /// 
+    /// Jobs with different keys still run in parallel. While a job for a key is queued or
+    /// running, the jobs behind it wait in the key's own lane rather than the queue, so they
+    /// don't hold up other keys or take up room in a bounded queue, and are queued one at a
+    /// time as the job ahead finishes. Keys are hashed, so on the rare occasion two keys hash
+    /// alike their jobs are also run one at a time.
+    /// 
+    /// ## Parameters
+    /// - `key`: What the job is ordered by, such as the path of the file it writes to.
+    /// - `f`: The job to execute. This must implement `FnOnce()`.
+    /// 
+    /// ## Returns
+    /// The same as `execute`. Only a job whose key has nothing queued or running can be
+    /// rejected for a full queue; the rest wait in the key's lane.
+    pub fn execute_keyed<K, F>(&self, key: &K, f: F) -> Result<(), ExecuteError<F>>
+    where
+        K: Hash + ?Sized,
+        F: FnOnce() + Send + 'static
+    {
+        self.shared.submit(Priority::Normal, f, None, None, Some(keyed::lane(key)), self.shared.policy)
+    }
+
+    /// Execute a job on the ThreadPool as part of a `JobGroup`, so it can be waited on with
+    /// the rest of the group.
+    /// 
+    /// The job joins the group only once it is accepted, and leaves it once it has finished,
+    /// panicked, or been dropped without running.
+    /// 
+    /// ## Parameters
+    /// - `group`: The group the job joins.
+    /// - `f`: The job to execute. This must implement `FnOnce()`.
+    /// 
+    /// ## Returns
+    /// The same as `execute`. A rejected job doesn't join the group.
+    pub fn execute_in_group<F>(&self, group: &JobGroup, f: F) -> Result<(), ExecuteError<F>>
+    where
+        F: FnOnce() + Send + 'static
+    {
+        let job = Grouped {
+            f,
+            group: group.clone(),
+        };
+        self.shared
+            .submit(Priority::Normal, job, None, None, None, self.shared.policy)
+            .map_err(|err| err.map(|job| job.f))
+    }
+
+    /// Execute a job on the ThreadPool once `delay` has passed.
+    /// 
+    /// The pool's supervisor thread keeps the job until it is due, then queues it at
+    /// `Priority::Normal`. If the queue is bounded and full at that point, the job is dropped
+    /// unless the pool's `RejectionPolicy` is `DiscardOldest`. Jobs that aren't due yet when
+    /// the pool shuts down never run.
+    /// 
+    /// ## Parameters
+    /// - `delay`: How long to wait before queueing the job.
+    /// - `f`: The job to execute. This must implement `FnOnce()`.
+    /// 
+    /// ## Returns
+    /// A `ScheduledJob` that can cancel the job until a worker starts it, or
+    /// `ExecuteError::Shutdown` holding `f` if the pool has shut down.
+    pub fn execute_after<F>(&self, delay: Duration, f: F) -> Result<ScheduledJob, ExecuteError<F>>
+    where
+        F: FnOnce() + Send + 'static
+    {
+        if self.shared.queue.is_closed() {
+            return Err(ExecuteError::Shutdown(f));
         }
+
+        let (timer, handle) = Timer::new(delay, Task::Once(Box::new(f)));
+        let _ = self.shared.events.send(SupervisorEvent::Schedule(timer));
+        Ok(handle)
     }
-}
 
-/// A worker that executes jobs.
-/// 
-/// ## Fields
-/// - `id`: The id of the worker.
-/// - `thread`: The thread that the worker is running on.
-struct Worker {
-    id: usize,
-    thread: Option<JoinHandle<()>>,
-}
+    /// Execute a job on the ThreadPool every `period`, starting one `period` from now.
+    /// 
+    /// Each run is queued like a job from `execute_after`. If a run comes due while the last
+    /// one is still going, it is skipped rather than running alongside it, and if the pool
+    /// falls a whole period behind it carries on from the current time rather than catching
+    /// up on the runs it missed.
+    /// 
+    /// ## Parameters
+    /// - `period`: How long between runs.
+    /// - `f`: The job to execute. This must implement `FnMut()`.
+    /// 
+    /// ## Returns
+    /// A `ScheduledJob` that stops the job from running again, or `ExecuteError::Shutdown`
+    /// holding `f` if the pool has shut down.
+    /// 
+    /// ## Panics
+    /// Panics if `period` is zero.
+    pub fn execute_every<F>(&self, period: Duration, f: F) -> Result<ScheduledJob, ExecuteError<F>>
+    where
+        F: FnMut() + Send + 'static
+    {
+        assert!(!period.is_zero(), "execute_every period must be greater than zero");
+
+        if self.shared.queue.is_closed() {
+            return Err(ExecuteError::Shutdown(f));
+        }
+
+        let (timer, handle) = Timer::new(period, Task::Every { period, f: Timer::periodic(f) });
+        let _ = self.shared.events.send(SupervisorEvent::Schedule(timer));
+        Ok(handle)
+    }
 
-impl Worker {
-    /// Create a new Worker.
+    /// Block until every job submitted so far has finished and the queue is empty, without
+    /// shutting the pool down.
+    /// 
+    /// Jobs submitted while waiting are waited for too. Delayed and periodic jobs only count
+    /// once they come due.
+    /// 
+    /// ## Panics
+    /// Panics if called from a job running on the pool, which would be waiting for itself.
+    pub fn wait_idle(&self) {
+        self.assert_not_worker("wait_idle");
+        self.shared.wait_idle(None);
+    }
+
+    /// Block until every job submitted so far has finished and the queue is empty, or
+    /// `timeout` has passed, whichever comes first.
     /// 
     /// ## Parameters
-    /// - `id`: The id of the worker.
-    /// - `receiver`: The receiver end of the channel.
+    /// - `timeout`: How long to wait.
     /// 
     /// ## Returns
-    /// A new `Worker` with the given id and receiver.
+    /// `true` if the pool went idle, `false` if jobs were still queued or running at the timeout.
     /// 
-    fn new(id: usize, receiver: Arc<Mutex<Receiver<Job>>>) -> Worker {
-        // Create a new thread that will run the worker's main loop.
-        let thread = thread::spawn(move || loop {
-            // Receive a job from the channel. If there are no more jobs, this call will block.
-            let message = receiver.lock().unwrap().recv();
+    /// ## Panics
+    /// Panics if called from a job running on the pool, which would be waiting for itself.
+    pub fn wait_idle_timeout(&self, timeout: Duration) -> bool {
+        self.assert_not_worker("wait_idle_timeout");
+        self.shared.wait_idle(Some(Instant::now() + timeout))
+    }
+
+    /// Panic if the current thread is one of the pool's workers.
+    fn assert_not_worker(&self, method: &str) {
+        assert!(
+            worker::current(&self.shared).is_none(),
+            "ThreadPool::{method} called from a job on the same pool, which would wait for itself",
+        );
+    }
+
+    /// The number of jobs waiting in the queue at each priority.
+    /// 
+    /// ## Returns
+    /// A snapshot of the queue depths. Jobs may be added or taken while it is being taken.
+    pub fn queue_depths(&self) -> QueueDepths {
+        self.shared.queue.depths()
+    }
 
-            // If the message is an error, the channel has been closed and the worker should shut down.
-            match message {
-                // If the message is Ok, execute the job.
-                Ok(job) => {
-                    println!("Worker {} got a job; executing.", id);
-                    job();
-                },
-                // If the message is an error, the channel has been closed and the worker should shut down.
-                Err(_) => {
-                    println!("Worker {} is shutting down.", id);
-                    break;
-                },
+    /// Take a snapshot of what the pool is doing and has done.
+    /// 
+    /// This only reads counters the workers keep as they go, so it is cheap enough to call
+    /// on every request.
+    /// 
+    /// ## Returns
+    /// A `PoolStats` with the current queue and worker counts, the number of jobs completed,
+    /// panicked and rejected so far, and how long jobs have waited and run. The figures are
+    /// read one at a time, so may be slightly out of step with each other.
+    pub fn stats(&self) -> PoolStats {
+        let shared = &self.shared;
+        let live = shared.live_workers.load(Ordering::Acquire);
+        let running = shared.busy_workers.load(Ordering::Acquire);
+
+        shared.metrics.stats(shared.queued.load(Ordering::Acquire), running, live.saturating_sub(running))
+    }
+
+    /// Run a job on the ThreadPool and get its result back through a `JobHandle`.
+    /// 
+    /// A panic in the job is caught and returned from the handle, rather than being passed
+    /// to the pool's panic handler.
+    /// 
+    /// ## Parameters
+    /// - `f`: The job to run. This must implement `FnOnce() -> T`.
+    /// 
+    /// ## Returns
+    /// A `JobHandle` for the job's result. If the pool rejects the job, joining the handle
+    /// returns `JoinError::Lost`.
+    pub fn spawn<F, T>(&self, f: F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+
+        // Nobody may be waiting for the result any more, so a failed send is fine. A rejected
+        // job is dropped along with the sender, which the handle sees as a lost result.
+        let _ = self.execute(move || {
+            let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(f)));
+        });
+
+        JobHandle::new(receiver)
+    }
+
+    /// Run a future on the ThreadPool and get its output back through a `JobHandle`.
+    /// 
+    /// The future is polled on the workers, a poll at a time, alongside the pool's other
+    /// jobs. Whenever it is woken, it is queued to be polled again, so a pending future
+    /// doesn't hold a worker while it waits. A panic while polling is caught and returned
+    /// from the handle, as with `spawn`.
+    /// 
+    /// A future still pending when the pool shuts down is dropped without finishing.
+    /// 
+    /// ## Parameters
+    /// - `future`: The future to run.
+    /// 
+    /// ## Returns
+    /// A `JobHandle` for the future's output. If the pool rejects or discards a poll of the
+    /// future, it is dropped, and joining the handle returns `JoinError::Lost`.
+    pub fn spawn_future<F>(&self, future: F) -> JobHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let task = FutureTask::new(future, sender, &self.shared);
+
+        // A rejected poll drops the future along with the sender.
+        let _ = self.execute(task.job());
+
+        JobHandle::new(receiver)
+    }
+
+    /// Run jobs on the pool that may borrow from the stack, like `std::thread::scope`.
+    /// 
+    /// `f` is given a `Scope` to execute jobs on. Every job executed on it has finished by
+    /// the time this returns, so jobs don't need everything they use to be `'static`.
+    /// 
+    /// It is safe to call from a job running on the pool. While it waits, the worker runs
+    /// other queued jobs, so the scope's jobs can't be stuck behind workers waiting on scopes.
+    /// If the pool is bounded and its queue is full, the worker runs the scope's jobs itself
+    /// rather than waiting for room, even with `RejectionPolicy::Block`.
+    /// 
+    /// ## Parameters
+    /// - `f`: Called with the scope on the calling thread.
+    /// 
+    /// ## Returns
+    /// The value returned by `f`.
+    /// 
+    /// ## Panics
+    /// Panics, once every job has finished, if `f` or any of the jobs panicked. The payload
+    /// of `f`'s panic, or otherwise the first job's, is passed on.
+    pub fn scope<'env, F, T>(&self, f: F) -> T
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T
+    {
+        let scope = Scope::new(self);
+
+        // Wait for the jobs even if `f` panics, since they may borrow from its caller.
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        let job_panic = scope.wait();
+
+        match (outcome, job_panic) {
+            (Err(payload), _) | (Ok(_), Some(payload)) => panic::resume_unwind(payload),
+            (Ok(value), None) => value,
+        }
+    }
+
+    /// Run two closures, potentially in parallel, and return both results.
+    /// 
+    /// `a` runs on the calling thread while `b` is executed on the pool. Like `scope`, the
+    /// closures may borrow from the stack, and it is safe to call from a job on the pool.
+    /// 
+    /// ## Parameters
+    /// - `a`: The closure to run on the calling thread.
+    /// - `b`: The closure to run on the pool, or on the calling thread if the pool rejects it.
+    /// 
+    /// ## Returns
+    /// The results of `a` and `b`.
+    /// 
+    /// ## Panics
+    /// Panics, once both have finished, if either closure panicked.
+    pub fn join<A, B, RA, RB>(&self, a: A, b: B) -> (RA, RB)
+    where
+        A: FnOnce() -> RA,
+        B: FnOnce() -> RB + Send,
+        RB: Send,
+    {
+        let mut rb = None;
+        let ra = self.scope(|scope| {
+            scope.execute(|| rb = Some(b()));
+            a()
+        });
+        (ra, rb.expect("the scope waits for every job"))
+    }
+
+    /// Apply `f` to every item, in parallel, and collect the results in the order of the items.
+    /// 
+    /// Each item is its own job, so use `for_each_chunked` when there are many items that
+    /// are each quick to handle. Like `scope`, `f` may borrow from the stack, and it is safe
+    /// to call from a job on the pool.
+    /// 
+    /// ## Parameters
+    /// - `items`: The items to map.
+    /// - `f`: The function to apply to each item.
+    /// 
+    /// ## Returns
+    /// The results, in the same order as `items`.
+    /// 
+    /// ## Panics
+    /// Panics, once every item has been handled, if `f` panicked on any of them.
+    pub fn map<I, F, T>(&self, items: I, f: F) -> Vec<T>
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) -> T + Sync,
+        T: Send,
+    {
+        let items: Vec<I::Item> = items.into_iter().collect();
+        let mut results: Vec<Option<T>> = items.iter().map(|_| None).collect();
+
+        let f = &f;
+        self.scope(|scope| {
+            for (item, result) in items.into_iter().zip(&mut results) {
+                scope.execute(move || *result = Some(f(item)));
+            }
+        });
+
+        results.into_iter().map(|result| result.expect("the scope waits for every job")).collect()
+    }
+
+    /// Call `f` on every item of a slice, in parallel, with a job for every `chunk_size` items.
+    /// 
+    /// Like `scope`, `f` may borrow from the stack, and it is safe to call from a job on the pool.
+    /// 
+    /// ## Parameters
+    /// - `items`: The items.
+    /// - `chunk_size`: How many items each job handles, one after another.
+    /// - `f`: The function to call on each item.
+    /// 
+    /// ## Panics
+    /// Panics if `chunk_size` is zero, or, once every chunk has been handled, if `f` panicked.
+    pub fn for_each_chunked<T, F>(&self, items: &[T], chunk_size: usize, f: F)
+    where
+        T: Sync,
+        F: Fn(&T) + Sync,
+    {
+        assert!(chunk_size > 0, "chunk_size must be non-zero");
+
+        let f = &f;
+        self.scope(|scope| {
+            for chunk in items.chunks(chunk_size) {
+                scope.execute(move || chunk.iter().for_each(f));
             }
         });
+    }
+
+    /// Change the number of workers in the pool.
+    /// 
+    /// The pool's `min_threads` becomes `size`, and new workers are started straight away
+    /// if there are fewer than that. A fixed-size pool stays fixed at `size`; an elastic
+    /// pool's `max_threads` is raised to `size` if it was lower. When shrinking, surplus
+    /// workers retire as they finish their current job or go idle.
+    /// 
+    /// ## Parameters
+    /// - `size`: The new number of threads in the pool.
+    /// 
+    /// ## Returns
+    /// `Ok(())` once the pool has at least `size` workers, or a `PoolCreationError` if
+    /// `size` is zero or a worker thread could not be spawned, in which case the workers
+    /// that did start are kept.
+    pub fn resize(&self, size: usize) -> Result<(), PoolCreationError> {
+        if size == 0 {
+            return Err(PoolCreationError::ZeroSize);
+        }
+
+        let shared = &self.shared;
+        if shared.keep_alive.is_some() {
+            shared.max_threads.fetch_max(size, Ordering::AcqRel);
+        } else {
+            shared.max_threads.store(size, Ordering::Release);
+        }
+        shared.min_threads.store(size, Ordering::Release);
+
+        shared.spawn_workers(size)
+    }
 
-        Worker { 
-            id, 
-            thread: Some(thread), 
+    /// Shut down the ThreadPool, giving the queued jobs until `timeout` to finish.
+    /// 
+    /// The pool stops accepting jobs straight away and the workers carry on with the ones
+    /// already queued. If they haven't all finished by the deadline, the jobs nobody has
+    /// started are dropped, and the workers still running a job are left to finish it on
+    /// their own threads rather than blocking the caller.
+    /// 
+    /// ## Parameters
+    /// - `timeout`: How long to wait for the queued jobs to finish.
+    /// 
+    /// ## Returns
+    /// A `ShutdownReport` listing the workers still busy at the deadline and the number of
+    /// jobs dropped, with the pool's stats once it had shut down.
+    pub fn shutdown(mut self, timeout: Duration) -> ShutdownReport {
+        let deadline = Instant::now() + timeout;
+        self.shared.queue.close();
+
+        let mut discarded = 0;
+        if !self.shared.join_workers_until(deadline) {
+            // Out of time: drop the jobs nobody has started, so the busy workers stop as soon
+            // as their current job is done.
+            discarded = self.shared.drain_queue().len();
+        }
+
+        self.stop_supervisor();
+
+        // Replacements the supervisor started may still be running a job as well.
+        self.shared.join_workers_until(deadline);
+
+        ShutdownReport {
+            busy_workers: self.shared.detach_workers(),
+            discarded,
+            stats: self.stats(),
+        }
+    }
+
+    /// Shut down the ThreadPool without running the jobs still in the queue.
+    /// 
+    /// The pool stops accepting jobs and the queue is emptied. Jobs that are already running
+    /// are left to finish on their own threads; this doesn't wait for them.
+    /// 
+    /// ## Returns
+    /// The jobs that were queued but never started, in roughly the order they would have run.
+    pub fn shutdown_now(mut self) -> Vec<Job> {
+        self.shared.queue.close();
+        let jobs = self.shared.drain_queue();
+
+        self.stop_supervisor();
+        self.shared.join_workers_until(Instant::now());
+        self.shared.detach_workers();
+
+        jobs
+    }
+
+    /// Stop the supervisor thread and wait for it to exit. Any deaths reported before this
+    /// are handled first.
+    fn stop_supervisor(&mut self) {
+        let _ = self.shared.events.send(SupervisorEvent::Shutdown);
+        if let Some(supervisor) = self.supervisor.take() {
+            let _ = supervisor.join();
         }
     }
+
+    /// Set a callback to be told about jobs that panic.
+    /// 
+    /// A panicking job doesn't take its worker down with it; the worker reports the panic
+    /// here and goes on to the next job. A panic in the handler itself is ignored. This
+    /// replaces any handler set before.
+    /// 
+    /// ## Parameters
+    /// - `handler`: Called on the worker's thread with the worker's id and the panic payload.
+    ///   Use [`panic_message`] to get at the message.
+    pub fn set_panic_handler<H>(&self, handler: H)
+    where
+        H: Fn(usize, &(dyn Any + Send)) + Send + Sync + 'static
+    {
+        *lock(&self.shared.panic_handler) = Some(Arc::new(handler));
+    }
 }
 
+/// Implement the `Drop` trait for `ThreadPool`.
+impl Drop for ThreadPool {
+    /// Shut down the ThreadPool.
+    fn drop(&mut self) {
+        // Close the queue to signal to the workers that they should shut down.
+        self.shared.queue.close();
+        
+        // Wait for the workers to finish the remaining jobs and shut down.
+        self.shared.join_workers();
+
+        // Stop the supervisor. Any deaths reported while joining are handled first.
+        self.stop_supervisor();
+
+        // Join any replacements the supervisor started before it stopped.
+        self.shared.join_workers();
+    }
+}
@@ -0,0 +1,325 @@
+use std::{array, sync::{atomic::Ordering, mpsc::RecvTimeoutError, PoisonError}, time::{Duration, Instant}};
+
+use crate::{mpmc::{Queue, Unbounded}, priority::{Priority, QueueDepths}, queue::QueuedJob, shared::lock, sync::{fence, AtomicU64, AtomicUsize, Condvar, Mutex}};
+
+/// The bit of `LockFreeQueue::state` that is set once the queue is closed. The bits below it
+/// count the pending jobs.
+const CLOSED: usize = 1 << (usize::BITS - 1);
+
+/// A job queue shared by every worker, with a lock-free queue for each priority, so that
+/// pushing and taking jobs never waits on a lock.
+/// 
+/// Locks are only taken to park a worker with nothing to do, or a push waiting for room in a
+/// full queue, and to wake them. A push or take only takes one to wake a thread that is
+/// parked.
+/// 
+/// Aging can't look at when the job at the front of a lane was queued without taking it,
+/// so a lane's age is measured from when a job was last taken from it, or from when it
+/// last went from empty to holding a job. The job at the front of a lane has waited at least
+/// that long, so a job is never promoted sooner than with the `Channel` scheduler, but may
+/// be promoted later.
+/// 
+/// ## Fields
+/// - `lanes`: The jobs at each priority, highest first.
+/// - `overflow`: Jobs that had already been let into a bounded pool, but found their lane
+///   full. They are taken before any lane.
+/// - `served`: When each lane last had a job taken from it, or last went from empty to
+///   holding a job, in nanoseconds since `epoch`.
+/// - `epoch`: When the queue was created.
+/// - `aging`: How long a job waits before it is treated as one priority level higher.
+/// - `capacity`: The most jobs a push waits to fit in, if the queue is bounded.
+/// - `state`: The number of jobs pushed and not yet taken, with `CLOSED` set once the pool
+///   has stopped sending jobs. A job is counted before it is put in a lane, and uncounted
+///   once it has been taken out.
+/// - `sleepers`: The number of workers parked waiting for a job.
+/// - `blocked`: The number of pushes parked waiting for room.
+/// - `sleep`: The lock parked workers and pushes wait on.
+/// - `not_empty`: Signalled when a job is pushed or the queue is closed.
+/// - `not_full`: Signalled when a job is taken from a bounded queue, or the queue is closed.
+pub(crate) struct LockFreeQueue {
+    lanes: [Queue<QueuedJob>; 3],
+    overflow: Unbounded<QueuedJob>,
+    served: [AtomicU64; 3],
+    epoch: Instant,
+    aging: Duration,
+    capacity: Option<usize>,
+    state: AtomicUsize,
+    sleepers: AtomicUsize,
+    blocked: AtomicUsize,
+    sleep: Mutex<()>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl LockFreeQueue {
+    /// Create an empty LockFreeQueue.
+    /// 
+    /// ## Parameters
+    /// - `capacity`: The most jobs the queue holds, or `None` for no limit.
+    /// - `aging`: How long a job waits before it is treated as one priority level higher.
+    pub(crate) fn new(capacity: Option<usize>, aging: Duration) -> LockFreeQueue {
+        LockFreeQueue {
+            lanes: array::from_fn(|_| Queue::new(capacity)),
+            overflow: Unbounded::new(),
+            served: array::from_fn(|_| AtomicU64::new(0)),
+            epoch: Instant::now(),
+            aging,
+            capacity,
+            state: AtomicUsize::new(0),
+            sleepers: AtomicUsize::new(0),
+            blocked: AtomicUsize::new(0),
+            sleep: Mutex::new(()),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        }
+    }
+
+    /// The time since the queue was created, in nanoseconds.
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_nanos().try_into().unwrap_or(u64::MAX)
+    }
+
+    /// Add a job to the queue, blocking while a bounded queue is full.
+    /// 
+    /// The job is counted and the queue checked for being open in one step, so a worker only
+    /// sees the queue as disconnected once every job accepted here has been taken.
+    /// 
+    /// ## Parameters
+    /// - `f`: The job to add.
+    /// - `priority`: How urgently the job should run.
+    /// - `job`: Wraps the job up to be queued. Only called once the job has been accepted.
+    /// 
+    /// ## Returns
+    /// `Ok(())` if the job was queued, or the job back if the queue has been closed.
+    pub(crate) fn push<F>(&self, f: F, priority: Priority, job: impl FnOnce(F) -> QueuedJob) -> Result<(), F> {
+        let mut state = self.state.load(Ordering::SeqCst);
+        loop {
+            if state & CLOSED != 0 {
+                return Err(f);
+            }
+            if self.is_full(state) {
+                state = self.wait_for_room();
+                continue;
+            }
+
+            match self.state.compare_exchange_weak(state, state + 1, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => break,
+                Err(current) => state = current,
+            }
+        }
+
+        self.enqueue(job(f), priority);
+        Ok(())
+    }
+
+    /// Whether a bounded queue is full, going by its state.
+    fn is_full(&self, state: usize) -> bool {
+        self.capacity.is_some_and(|capacity| state & !CLOSED >= capacity)
+    }
+
+    /// Park until a job is taken from a full queue, or the queue is closed.
+    /// 
+    /// ## Returns
+    /// The queue's state once woken.
+    fn wait_for_room(&self) -> usize {
+        let sleep = lock(&self.sleep);
+        self.blocked.fetch_add(1, Ordering::SeqCst);
+        fence(Ordering::SeqCst);
+
+        // Check again now that we're counted as blocked, so a take since the last check
+        // either shows up here or wakes us.
+        let state = self.state.load(Ordering::SeqCst);
+        let sleep = if state & CLOSED == 0 && self.is_full(state) {
+            self.not_full.wait(sleep).unwrap_or_else(PoisonError::into_inner)
+        } else {
+            sleep
+        };
+
+        self.blocked.fetch_sub(1, Ordering::SeqCst);
+        drop(sleep);
+        self.state.load(Ordering::SeqCst)
+    }
+
+    /// Add a job to the queue without waiting for room, even if the queue has been closed.
+    /// 
+    /// ## Parameters
+    /// - `job`: The job to add.
+    /// - `priority`: How urgently the job should run.
+    pub(crate) fn push_ready(&self, job: QueuedJob, priority: Priority) {
+        // Count the job before it is visible, so no worker can take it and uncount it first.
+        self.state.fetch_add(1, Ordering::SeqCst);
+        self.enqueue(job, priority);
+    }
+
+    /// Put a job that has already been counted where a worker will find it, and wake a
+    /// parked worker to run it.
+    fn enqueue(&self, job: QueuedJob, priority: Priority) {
+        let lane = priority.lane();
+        let was_empty = self.lanes[lane].is_empty();
+
+        // A job let in past the capacity by `push_ready` may find its lane full.
+        if let Err(job) = self.lanes[lane].push(job) {
+            self.overflow.push(job);
+        }
+        if was_empty {
+            self.served[lane].store(self.now(), Ordering::Relaxed);
+        }
+
+        // Pairs with the fence in `pop`: either the worker finds the job, or we see it parked.
+        // A worker holds the lock from counting itself until it waits, so once we have had the
+        // lock it is waiting, and is woken without having to wait for the lock again.
+        fence(Ordering::SeqCst);
+        if self.sleepers.load(Ordering::Relaxed) > 0 {
+            drop(lock(&self.sleep));
+            self.not_empty.notify_one();
+        }
+    }
+
+    /// Take a job for a worker, waiting up to `timeout` for one to arrive.
+    /// 
+    /// ## Parameters
+    /// - `timeout`: How long to wait for a job.
+    /// 
+    /// ## Returns
+    /// The job, `RecvTimeoutError::Timeout` if none arrived in time, or
+    /// `RecvTimeoutError::Disconnected` once the queue is closed and empty.
+    pub(crate) fn pop(&self, timeout: Duration) -> Result<QueuedJob, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(job) = self.take() {
+                return Ok(job);
+            }
+
+            // Look again once counted as a sleeper, so a job pushed since the last look
+            // either turns up now or wakes us. A job that is counted but not found has either
+            // just been taken by someone else, or is on its way in and its pusher will wake us.
+            let sleep = lock(&self.sleep);
+            self.sleepers.fetch_add(1, Ordering::SeqCst);
+            fence(Ordering::SeqCst);
+            let job = self.next();
+            let state = self.state.load(Ordering::SeqCst);
+            let now = Instant::now();
+
+            if job.is_some() || state == CLOSED || now >= deadline {
+                self.sleepers.fetch_sub(1, Ordering::SeqCst);
+                drop(sleep);
+
+                return match job {
+                    Some(job) => {
+                        // Uncounting the job may wake others, which takes the lock again.
+                        self.taken(1);
+                        Ok(job)
+                    },
+                    None if state == CLOSED => Err(RecvTimeoutError::Disconnected),
+                    None => Err(RecvTimeoutError::Timeout),
+                };
+            }
+
+            let (sleep, _) = self.not_empty.wait_timeout(sleep, deadline - now).unwrap_or_else(PoisonError::into_inner);
+            self.sleepers.fetch_sub(1, Ordering::SeqCst);
+            drop(sleep);
+        }
+    }
+
+    /// Take the job that should run next, without waiting.
+    fn take(&self) -> Option<QueuedJob> {
+        let job = self.next()?;
+        self.taken(1);
+        Some(job)
+    }
+
+    /// Take the job that should run next out of its lane, leaving it counted in `state`.
+    /// 
+    /// Each lane is promoted one level for every `aging` since it was last served, and the
+    /// highest of those is tried first, the one served longest ago winning a tie.
+    fn next(&self) -> Option<QueuedJob> {
+        self.overflow.pop().or_else(|| {
+            let now = self.now();
+            let aging = self.aging.as_nanos().max(1);
+
+            let mut lanes: [(usize, u64, usize); 3] = array::from_fn(|lane| {
+                let served = self.served[lane].load(Ordering::Relaxed);
+                let promotions = u128::from(now.saturating_sub(served)) / aging;
+                (lane.saturating_sub(promotions.try_into().unwrap_or(usize::MAX)), served, lane)
+            });
+            lanes.sort_unstable();
+
+            lanes.iter().find_map(|&(_, _, lane)| {
+                let job = self.lanes[lane].pop()?;
+                self.served[lane].store(now, Ordering::Relaxed);
+                Some(job)
+            })
+        })
+    }
+
+    /// Uncount jobs that have been taken, and wake whoever may be waiting on them.
+    fn taken(&self, count: usize) {
+        let state = self.state.fetch_sub(count, Ordering::SeqCst) - count;
+
+        // A worker may have parked on a job it saw counted but someone else took. Once the
+        // last job is gone from a closed queue, it would otherwise never be woken.
+        if state == CLOSED {
+            let _sleep = lock(&self.sleep);
+            self.not_empty.notify_all();
+        }
+
+        // Pairs with the fence in `wait_for_room`: either the push sees the room, or we see
+        // it parked.
+        if self.capacity.is_some() {
+            fence(Ordering::SeqCst);
+            if self.blocked.load(Ordering::Relaxed) > 0 {
+                drop(lock(&self.sleep));
+                if count == 1 {
+                    self.not_full.notify_one();
+                } else {
+                    self.not_full.notify_all();
+                }
+            }
+        }
+    }
+
+    /// Take the lowest-priority job that has been queued the longest, if there is one.
+    pub(crate) fn pop_oldest(&self) -> Option<QueuedJob> {
+        let job = self.lanes.iter().rev().find_map(Queue::pop).or_else(|| self.overflow.pop())?;
+        self.taken(1);
+        Some(job)
+    }
+
+    /// Take every queued job, in roughly the order they would have run.
+    pub(crate) fn drain(&self) -> Vec<QueuedJob> {
+        let mut jobs: Vec<QueuedJob> = std::iter::from_fn(|| self.overflow.pop()).collect();
+        for lane in &self.lanes {
+            jobs.extend(std::iter::from_fn(|| lane.pop()));
+        }
+
+        if !jobs.is_empty() {
+            self.taken(jobs.len());
+        }
+        jobs
+    }
+
+    /// The number of jobs waiting at each priority. Jobs let into a full bounded queue are
+    /// counted as `Priority::Normal`.
+    pub(crate) fn depths(&self) -> QueueDepths {
+        QueueDepths {
+            high: self.lanes[Priority::High.lane()].len(),
+            normal: self.lanes[Priority::Normal.lane()].len() + self.overflow.len(),
+            low: self.lanes[Priority::Low.lane()].len(),
+        }
+    }
+
+    /// Whether the queue has been closed.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.state.load(Ordering::SeqCst) & CLOSED != 0
+    }
+
+    /// Stop accepting jobs, and wake every parked worker and push.
+    pub(crate) fn close(&self) {
+        self.state.fetch_or(CLOSED, Ordering::SeqCst);
+
+        let _sleep = lock(&self.sleep);
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+}
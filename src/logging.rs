@@ -0,0 +1,352 @@
+use std::{collections::HashMap, error::Error, fmt, fs::{File, OpenOptions}, io::{self, LineWriter, Write}, path::Path, str::FromStr, sync::{atomic::{AtomicU8, Ordering}, Arc, OnceLock, PoisonError, RwLock}, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::{shared::lock, sync::Mutex};
+
+/// The logger used when none has been set with `set_logger`.
+static LOGGER: OnceLock<RwLock<Arc<Logger>>> = OnceLock::new();
+
+/// The most detailed level the global logger logs for any component, as stored by
+/// `encode_max`, so events it would filter out can be skipped without taking its lock.
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(encode_max(Some(Level::Info)));
+
+/// Store a most detailed level in a byte: 0 for none, otherwise one more than the level.
+const fn encode_max(level: Option<Level>) -> u8 {
+    match level {
+        Some(level) => level as u8 + 1,
+        None => 0,
+    }
+}
+
+/// How important a log event is.
+/// 
+/// ## Variants
+/// - `Error`: Something failed and the pool couldn't recover, such as a worker that
+///   couldn't be replaced.
+/// - `Warn`: Something went wrong but the pool carried on, such as a worker dying.
+/// - `Info`: Workers starting, retiring and shutting down.
+/// - `Debug`: Every job a worker picks up.
+/// - `Trace`: Finer detail than `Debug`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    /// The level's name in upper case, as written by the built-in sinks.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl FromStr for Level {
+    type Err = ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Level, ParseLevelError> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            "trace" => Ok(Level::Trace),
+            _ => Err(ParseLevelError(s.to_string())),
+        }
+    }
+}
+
+/// An error returned when a level, or a filter naming one, could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseLevelError(String);
+
+impl fmt::Display for ParseLevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid log level {:?}, expected off, error, warn, info, debug or trace", self.0)
+    }
+}
+
+impl Error for ParseLevelError {}
+
+/// Parse a level, or `off` for none.
+fn parse_filter_level(s: &str) -> Result<Option<Level>, ParseLevelError> {
+    if s.eq_ignore_ascii_case("off") {
+        Ok(None)
+    } else {
+        s.parse().map(Some)
+    }
+}
+
+/// Something that happened in the pool or the server, as handed to a `LogSink`.
+/// 
+/// ## Fields
+/// - `time`: When the event happened.
+/// - `level`: How important the event is.
+/// - `component`: The part of the program the event came from: `pool`, `worker`,
+///   `supervisor`, `watchdog` or `server`.
+/// - `message`: What happened.
+/// - `fields`: Details of the event, such as the id of the worker it happened on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEvent {
+    pub time: SystemTime,
+    pub level: Level,
+    pub component: &'static str,
+    pub message: String,
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl LogEvent {
+    /// The value of the field called `name`, if the event has one.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|(field, _)| *field == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Formats the event as one line: the time in seconds since the Unix epoch, the level, the
+/// component, the message and then each field as `name=value`.
+impl fmt::Display for LogEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let since_epoch = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        write!(
+            f,
+            "{}.{:03} {:<5} {}: {}",
+            since_epoch.as_secs(),
+            since_epoch.subsec_millis(),
+            self.level,
+            self.component,
+            self.message,
+        )?;
+
+        for (name, value) in &self.fields {
+            write!(f, " {name}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a `Logger` sends the events it lets through.
+pub trait LogSink: Send + Sync {
+    /// Record an event.
+    fn log(&self, event: &LogEvent);
+
+    /// Write out anything the sink has buffered.
+    fn flush(&self) {}
+}
+
+/// A `LogSink` that writes each event as a line to stderr.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StderrSink;
+
+impl LogSink for StderrSink {
+    fn log(&self, event: &LogEvent) {
+        eprintln!("{event}");
+    }
+}
+
+/// A `LogSink` that appends each event as a line to a file.
+/// 
+/// ## Fields
+/// - `file`: The file, written a line at a time.
+pub struct FileSink {
+    file: Mutex<LineWriter<File>>,
+}
+
+impl FileSink {
+    /// Open a file to log to, creating it if it doesn't exist and appending if it does.
+    /// 
+    /// ## Parameters
+    /// - `path`: The path of the file.
+    /// 
+    /// ## Returns
+    /// The new `FileSink`, or the `io::Error` returned when opening the file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<FileSink> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileSink {
+            file: Mutex::new(LineWriter::new(file)),
+        })
+    }
+}
+
+impl LogSink for FileSink {
+    fn log(&self, event: &LogEvent) {
+        // There's nowhere left to report a failure to log to, so it is dropped.
+        let _ = writeln!(lock(&self.file), "{event}");
+    }
+
+    fn flush(&self) {
+        let _ = lock(&self.file).flush();
+    }
+}
+
+/// A `LogSink` that keeps every event in memory, for tests to check what was logged.
+/// 
+/// ## Fields
+/// - `events`: Every event logged so far.
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    events: Mutex<Vec<LogEvent>>,
+}
+
+impl MemorySink {
+    /// Create an empty MemorySink.
+    pub fn new() -> MemorySink {
+        MemorySink::default()
+    }
+
+    /// A copy of every event logged so far, oldest first.
+    pub fn events(&self) -> Vec<LogEvent> {
+        lock(&self.events).clone()
+    }
+
+    /// Take every event logged so far, leaving the sink empty.
+    pub fn take(&self) -> Vec<LogEvent> {
+        std::mem::take(&mut *lock(&self.events))
+    }
+}
+
+impl LogSink for MemorySink {
+    fn log(&self, event: &LogEvent) {
+        lock(&self.events).push(event.clone());
+    }
+}
+
+/// Decides which events to log, per component, and hands them to a `LogSink`.
+/// 
+/// Events from a component are logged if they are at least as important as the component's
+/// level, or the logger's default level if the component doesn't have one. Events that are
+/// filtered out are never formatted, so logging at `Debug` costs next to nothing when only
+/// `Info` is enabled.
+/// 
+/// ## Fields
+/// - `sink`: Where the events go.
+/// - `level`: The least important level logged for components without their own, or
+///   `None` to log nothing.
+/// - `components`: The levels set for particular components.
+pub struct Logger {
+    sink: Arc<dyn LogSink>,
+    level: Option<Level>,
+    components: HashMap<String, Option<Level>>,
+}
+
+impl Logger {
+    /// Create a Logger that sends events at `Level::Info` and above to `sink`.
+    pub fn new(sink: Arc<dyn LogSink>) -> Logger {
+        Logger {
+            sink,
+            level: Some(Level::Info),
+            components: HashMap::new(),
+        }
+    }
+
+    /// Set the least important level logged for components without their own, or `None` to
+    /// log nothing from them.
+    pub fn with_level(mut self, level: Option<Level>) -> Logger {
+        self.level = level;
+        self
+    }
+
+    /// Set the least important level logged for one component, or `None` to log nothing
+    /// from it.
+    pub fn with_component_level(mut self, component: impl Into<String>, level: Option<Level>) -> Logger {
+        self.components.insert(component.into(), level);
+        self
+    }
+
+    /// Set levels from a comma-separated filter, such as `warn,worker=debug,supervisor=off`.
+    /// A bare level sets the default; `component=level` sets a component's.
+    /// 
+    /// ## Parameters
+    /// - `filter`: The filter, typically read from the environment at startup.
+    /// 
+    /// ## Returns
+    /// The updated `Logger`, or a `ParseLevelError` if the filter names an unknown level.
+    pub fn with_filter(mut self, filter: &str) -> Result<Logger, ParseLevelError> {
+        for directive in filter.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            match directive.split_once('=') {
+                Some((component, level)) => {
+                    self.components.insert(component.trim().to_string(), parse_filter_level(level.trim())?);
+                },
+                None => self.level = parse_filter_level(directive)?,
+            }
+        }
+        Ok(self)
+    }
+
+    /// Whether an event at `level` from `component` would be logged.
+    pub fn enabled(&self, level: Level, component: &str) -> bool {
+        let max = self.components.get(component).copied().unwrap_or(self.level);
+        max.is_some_and(|max| level <= max)
+    }
+
+    /// The most detailed level logged for any component, or `None` if nothing is logged.
+    pub fn max_level(&self) -> Option<Level> {
+        self.components.values().copied().fold(self.level, Option::max)
+    }
+
+    /// Log an event, if its level is enabled for its component.
+    /// 
+    /// ## Parameters
+    /// - `level`: How important the event is.
+    /// - `component`: The part of the program the event came from.
+    /// - `message`: What happened. Only formatted if the event is logged.
+    /// - `fields`: Details of the event as name and value pairs.
+    pub fn log(&self, level: Level, component: &'static str, message: fmt::Arguments<'_>, fields: &[(&'static str, &dyn fmt::Display)]) {
+        if !self.enabled(level, component) {
+            return;
+        }
+
+        self.sink.log(&LogEvent {
+            time: SystemTime::now(),
+            level,
+            component,
+            message: message.to_string(),
+            fields: fields.iter().map(|(name, value)| (*name, value.to_string())).collect(),
+        });
+    }
+
+    /// Write out anything the sink has buffered.
+    pub fn flush(&self) {
+        self.sink.flush();
+    }
+}
+
+/// The global logger, starting out as one that writes `Level::Info` and above to stderr.
+fn global() -> &'static RwLock<Arc<Logger>> {
+    LOGGER.get_or_init(|| RwLock::new(Arc::new(Logger::new(Arc::new(StderrSink)))))
+}
+
+/// Replace the global logger, used by pools that weren't given their own with
+/// `ThreadPoolBuilder::logger`.
+pub fn set_logger(logger: Logger) {
+    let max = encode_max(logger.max_level());
+    let mut global = global().write().unwrap_or_else(PoisonError::into_inner);
+    *global = Arc::new(logger);
+    MAX_LEVEL.store(max, Ordering::Release);
+}
+
+/// Get the global logger.
+pub fn logger() -> Arc<Logger> {
+    Arc::clone(&global().read().unwrap_or_else(PoisonError::into_inner))
+}
+
+/// Log an event to the global logger, if it logs events at `level` from any component.
+/// Checking the level first means an event nobody wants costs one atomic load, rather than
+/// taking the global logger's lock.
+pub(crate) fn log(level: Level, component: &'static str, message: fmt::Arguments<'_>, fields: &[(&'static str, &dyn fmt::Display)]) {
+    if encode_max(Some(level)) <= MAX_LEVEL.load(Ordering::Acquire) {
+        logger().log(level, component, message, fields);
+    }
+}
@@ -1,24 +1,122 @@
-use std::{fs, io::{Read, Write}, net::{TcpListener, TcpStream}, thread};
-use server_rs::ThreadPool;
+use std::{env, fs, io::{Read, Write}, net::{TcpListener, TcpStream}, sync::Arc, thread, time::Duration};
+use server_rs::{logging::{self, Level, Logger, StderrSink}, panic_message, Executor, InlineExecutor, RejectionPolicy, ThreadPerJob, ThreadPool};
+
+/// How long a connection may take before the pool's watchdog reports it as stuck.
+const CONNECTION_DEADLINE: Duration = Duration::from_secs(10);
+
+/// How long the connections still being handled are given to finish once the server stops.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// The main function.
 fn main() {
+    // Log to stderr, at the levels set in `SERVER_RS_LOG` (such as `warn,worker=debug`) if there are any.
+    let logger = Logger::new(Arc::new(StderrSink));
+    match env::var("SERVER_RS_LOG").map(|filter| logger.with_filter(&filter)) {
+        Ok(Ok(logger)) => logging::set_logger(logger),
+        Ok(Err(err)) => eprintln!("Ignoring SERVER_RS_LOG: {err}"),
+        Err(_) => {},
+    }
+    let log = logging::logger();
+
     // Create a new `TcpListener` bound to `localhost:7878`.
     let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
 
-    // Create a new `ThreadPool` with 4 threads.
-    let pool = ThreadPool::new(4);
+    // Handle the connections with the executor named in `SERVER_RS_EXECUTOR`: `pool` (the
+    // default) for a `ThreadPool`, `thread` for a thread per connection, or `inline` to handle
+    // each connection on this thread before accepting the next.
+    match env::var("SERVER_RS_EXECUTOR").as_deref() {
+        Ok("pool") | Err(_) => {
+            if let Some(pool) = start_pool() {
+                serve(&listener, pool);
+            }
+        },
+        Ok("thread") => serve(&listener, ThreadPerJob::new()),
+        Ok("inline") => serve(&listener, InlineExecutor::new()),
+        Ok(other) => log.log(
+            Level::Error,
+            "server",
+            format_args!("unknown SERVER_RS_EXECUTOR {other:?}, expected pool, thread or inline"),
+            &[],
+        ),
+    }
+}
+
+/// Start the `ThreadPool` the server uses by default.
+/// 
+/// ## Returns
+/// The pool, or `None` if it couldn't be created, in which case the reason has been logged.
+fn start_pool() -> Option<ThreadPool> {
+    // Create a new `ThreadPool` with 4 threads and room for 16 waiting connections. Once the
+    // queue is full, new connections are rejected straight away. If the pool can't be created,
+    // report why.
+    let pool = match ThreadPool::build_bounded(4, 16, RejectionPolicy::Abort) {
+        Ok(pool) => pool,
+        Err(err) => {
+            logging::logger().log(Level::Error, "server", format_args!("failed to start thread pool: {err}"), &[]);
+            return None;
+        }
+    };
+
+    // Report connections whose handler panicked. The worker itself carries on with the next connection.
+    pool.set_panic_handler(|id, payload| {
+        logging::logger().log(
+            Level::Error,
+            "server",
+            format_args!("worker panicked while handling a connection: {}", panic_message(payload)),
+            &[("worker", &id)],
+        );
+    });
+
+    Some(pool)
+}
+
+/// Accept connections and hand each one to `executor`, then shut it down.
+/// 
+/// ## Parameters
+/// - `listener`: The listener to accept connections from.
+/// - `executor`: Runs `handle_connection` for each connection.
+/// 
+fn serve<E: Executor>(listener: &TcpListener, executor: E) {
+    let log = logging::logger();
 
     // Listen for incoming connections.
     for stream in listener.incoming().take(2) {
         // Unwrap the stream. If it's `None`, print an error and continue.
         let stream = stream.unwrap();
 
-        // Execute the `handle_connection` function on the `ThreadPool`.
-        pool.execute(|| handle_connection(stream));
+        // Keep a second handle to the connection so it can still be answered if the executor rejects the job.
+        let fallback = stream.try_clone();
 
-        // Print a message to the console.
-        print!("Shutting down.")
+        // Execute the `handle_connection` function on the executor, with a warning logged if it
+        // takes longer than `CONNECTION_DEADLINE`. If the executor can't take it, answer 503.
+        if let Err(err) = executor.execute_with_deadline("connection", CONNECTION_DEADLINE, || handle_connection(stream)) {
+            log.log(Level::Warn, "server", format_args!("rejected connection: {err}"), &[]);
+            if let Ok(fallback) = fallback {
+                reject_connection(fallback);
+            }
+        }
+    }
+
+    // Log that the server is shutting down.
+    log.log(Level::Info, "server", format_args!("shutting down"), &[]);
+
+    // Give the connections still being handled time to finish, and report how the executor did,
+    // including the connections that finished while it drained.
+    let report = executor.shutdown(SHUTDOWN_TIMEOUT);
+    let stats = &report.stats;
+    log.log(
+        Level::Info,
+        "server",
+        format_args!("handled connections"),
+        &[("completed", &stats.completed), ("panicked", &stats.panicked), ("rejected", &stats.rejected), ("running", &stats.running)],
+    );
+    if !report.is_complete() {
+        log.log(
+            Level::Warn,
+            "server",
+            format_args!("connections still running at shutdown"),
+            &[("busy", &report.busy_workers.len()), ("discarded", &report.discarded)],
+        );
     }
 }
 
@@ -30,8 +128,9 @@ fn main() {
 fn handle_connection(mut stream: TcpStream) {
     // Create a buffer to hold the incoming data.
     let mut buffer = [0; 1024];
-    // Read the incoming data into the buffer.
-    stream.read(&mut buffer).unwrap();
+    // Read the incoming data into the buffer, keeping only the bytes actually received.
+    let bytes_read = stream.read(&mut buffer).unwrap();
+    let buffer = &buffer[..bytes_read];
 
     // Define the `GET` and `SLEEP` requests.
     let get = b"GET / HTTP/1.1\r\n";
@@ -44,7 +143,7 @@ fn handle_connection(mut stream: TcpStream) {
     // If the request is `GET /sleep`, sleep for 5 seconds and return `200 OK` and `hello.html`.
     } else if buffer.starts_with(sleep) {
         // Sleep for 5 seconds.
-        thread::sleep(std::time::Duration::from_secs(5));
+        thread::sleep(Duration::from_secs(5));
         // Return `200 OK` and `hello.html`.
         ("HTTP/1.1 200 OK", "hello.html")
     } else {
@@ -62,4 +161,16 @@ fn handle_connection(mut stream: TcpStream) {
     stream.write_all(response.as_bytes()).unwrap();
     // Flush the stream to ensure all data is written. This will also close the connection.
     stream.flush().unwrap();
-}
\ No newline at end of file
+}
+
+/// Answer a connection that the `ThreadPool` couldn't accept with `503 SERVICE UNAVAILABLE`.
+/// 
+/// ## Parameters
+/// - `stream`: The incoming `TcpStream`.
+/// 
+fn reject_connection(mut stream: TcpStream) {
+    // Tell the client to try again later. Errors are ignored, there is nothing more we can do for this client.
+    let response = "HTTP/1.1 503 SERVICE UNAVAILABLE\r\nContent-Length: 0\r\nRetry-After: 1\r\n\r\n";
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.flush();
+}
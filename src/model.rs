@@ -0,0 +1,362 @@
+// Model tests for the pool's queues and waits, run under every interleaving of their threads
+// by loom. They only build with `--cfg loom`:
+//
+//     RUSTFLAGS="--cfg loom" cargo test --release --lib
+//
+// Loom's waits never time out, so a thread that would only have woken because a wait timed
+// out shows up as a deadlock, which is how a shutdown hang is caught.
+
+use std::{sync::{atomic::Ordering, mpsc::RecvTimeoutError, Arc}, time::Duration};
+
+use loom::{model::Builder, thread};
+
+use crate::{group::Pending, keyed::KeyedLanes, logging::{Logger, StderrSink}, mpmc::{Bounded, Unbounded}, priority::{Priority, DEFAULT_AGING}, queue::{JobQueue, QueuedJob, Scheduler}, shared::lock, sync::{AtomicBool, AtomicUsize}, RejectionPolicy, ThreadPool};
+
+/// How long a worker waits for a job. Loom never times the wait out, so this is only
+/// here to be passed.
+const WAIT: Duration = Duration::from_secs(3600);
+
+/// Run a model, bounding how many times a thread may be preempted so the larger models
+/// finish in seconds.
+fn model(f: impl Fn() + Sync + Send + 'static) {
+    let mut builder = Builder::new();
+    builder.preemption_bound = Some(3);
+    builder.check(f);
+}
+
+/// A job that counts how many times it has run.
+fn counted(runs: &Arc<AtomicUsize>) -> QueuedJob {
+    let runs = Arc::clone(runs);
+    let job = Box::new(move || {
+        runs.fetch_add(1, Ordering::SeqCst);
+    });
+    QueuedJob::new(job, None, None, None)
+}
+
+/// Run jobs from the queue as a worker does, until it is closed and empty.
+fn work(queue: &JobQueue, worker: usize) {
+    loop {
+        match queue.recv_timeout(worker, WAIT) {
+            Ok(job) => (job.job)(),
+            Err(RecvTimeoutError::Disconnected) => return,
+            Err(RecvTimeoutError::Timeout) => {},
+        }
+    }
+}
+
+/// Submit a job while the queue closes and workers run, and check that the job runs exactly
+/// once if it was accepted and never if it was refused, and that every worker exits.
+fn check_close_races_push(scheduler: Scheduler, capacity: Option<usize>, workers: usize) {
+    model(move || {
+        let queue = Arc::new(JobQueue::new(scheduler, capacity, DEFAULT_AGING));
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let submitter = {
+            let (queue, runs) = (Arc::clone(&queue), Arc::clone(&runs));
+            thread::spawn(move || queue.push((), Priority::Normal, |()| counted(&runs)).is_ok())
+        };
+        let workers: Vec<_> = (0..workers)
+            .map(|worker| {
+                let queue = Arc::clone(&queue);
+                thread::spawn(move || work(&queue, worker))
+            })
+            .collect();
+
+        queue.close();
+
+        let accepted = submitter.join().unwrap();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), usize::from(accepted), "accepted job lost or run twice");
+    });
+}
+
+#[test]
+fn lock_free_queue_runs_every_job_accepted_before_close() {
+    check_close_races_push(Scheduler::LockFree, None, 1);
+}
+
+#[test]
+fn lock_free_queue_hands_each_job_to_one_worker() {
+    check_close_races_push(Scheduler::LockFree, None, 2);
+}
+
+#[test]
+fn bounded_lock_free_queue_runs_every_job_accepted_before_close() {
+    check_close_races_push(Scheduler::LockFree, Some(1), 1);
+}
+
+#[test]
+fn channel_queue_runs_every_job_accepted_before_close() {
+    check_close_races_push(Scheduler::Channel, None, 1);
+}
+
+#[test]
+fn channel_queue_hands_each_job_to_one_worker() {
+    check_close_races_push(Scheduler::Channel, None, 2);
+}
+
+#[test]
+fn stealing_queue_runs_every_job_accepted_before_close() {
+    check_close_races_push(Scheduler::WorkStealing, None, 1);
+}
+
+#[test]
+fn stealing_queue_hands_each_job_to_one_worker() {
+    check_close_races_push(Scheduler::WorkStealing, None, 2);
+}
+
+#[test]
+fn close_wakes_push_blocked_on_full_queue() {
+    check_close_wakes_blocked_push(Scheduler::Channel);
+}
+
+#[test]
+fn close_wakes_push_blocked_on_full_lock_free_queue() {
+    check_close_wakes_blocked_push(Scheduler::LockFree);
+}
+
+#[test]
+fn take_wakes_push_blocked_on_full_lock_free_queue() {
+    model(|| {
+        let queue = Arc::new(JobQueue::new(Scheduler::LockFree, Some(1), DEFAULT_AGING));
+        let runs = Arc::new(AtomicUsize::new(0));
+        queue.push((), Priority::Normal, |()| counted(&runs)).ok().unwrap();
+
+        // Only taking the queued job makes room for this push.
+        let blocked = {
+            let (queue, runs) = (Arc::clone(&queue), Arc::clone(&runs));
+            thread::spawn(move || queue.push((), Priority::Normal, |()| counted(&runs)).is_ok())
+        };
+
+        for _ in 0..2 {
+            (queue.recv_timeout(0, WAIT).ok().unwrap().job)();
+        }
+        assert!(blocked.join().unwrap());
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    });
+}
+
+/// Block a push on a full queue, and check that closing the queue wakes it and refuses the job.
+fn check_close_wakes_blocked_push(scheduler: Scheduler) {
+    model(move || {
+        let queue = Arc::new(JobQueue::new(scheduler, Some(1), DEFAULT_AGING));
+        let runs = Arc::new(AtomicUsize::new(0));
+        queue.push((), Priority::Normal, |()| counted(&runs)).unwrap();
+
+        // With no worker to make room, only closing the queue can wake this push.
+        let blocked = {
+            let (queue, runs) = (Arc::clone(&queue), Arc::clone(&runs));
+            thread::spawn(move || queue.push((), Priority::Normal, |()| counted(&runs)).is_ok())
+        };
+
+        queue.close();
+        assert!(!blocked.join().unwrap(), "push accepted a job after the queue closed");
+        assert_eq!(queue.drain().len(), 1);
+    });
+}
+
+#[test]
+fn job_passed_on_while_draining_still_runs() {
+    model(|| {
+        let queue = Arc::new(JobQueue::new(Scheduler::Channel, None, DEFAULT_AGING));
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        // A job that queues another as it finishes, as a keyed job passes its lane on.
+        let next = {
+            let (queue, runs) = (Arc::clone(&queue), Arc::clone(&runs));
+            move || queue.push_ready(counted(&runs), Priority::Normal)
+        };
+        queue.push(next, Priority::Normal, |next| QueuedJob::new(Box::new(next), None, None, None)).ok().unwrap();
+
+        let worker = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || work(&queue, 0))
+        };
+        queue.close();
+        worker.join().unwrap();
+
+        // Break the cycle between the queue and the job that held it.
+        drop(queue.drain());
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    });
+}
+
+#[test]
+fn job_left_by_exiting_stealing_worker_still_runs() {
+    model(|| {
+        let queue = Arc::new(JobQueue::new(Scheduler::WorkStealing, None, DEFAULT_AGING));
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        // A worker that queues a job on its own deque, then exits before running it.
+        let exiting = {
+            let (queue, runs) = (Arc::clone(&queue), Arc::clone(&runs));
+            thread::spawn(move || {
+                queue.register(0);
+                queue.push((), Priority::Normal, |()| counted(&runs)).ok().unwrap();
+                queue.unregister(0);
+            })
+        };
+        let worker = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || {
+                queue.register(1);
+                work(&queue, 1);
+                queue.unregister(1);
+            })
+        };
+
+        exiting.join().unwrap();
+        queue.close();
+        worker.join().unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 1, "job left on an exiting worker's deque was lost");
+    });
+}
+
+/// Drop a one-worker pool while another thread executes a job on it, and check that the job
+/// runs exactly once if it was accepted and never if it was refused, and that the worker and
+/// supervisor threads have been joined, and the worker counted out, by the time `drop` returns.
+fn check_drop_races_execute(scheduler: Scheduler) {
+    model(move || {
+        let logger = Logger::new(Arc::new(StderrSink)).with_level(None);
+        let pool = ThreadPool::builder().num_threads(1).scheduler(scheduler).logger(logger).build().unwrap();
+        let shared = Arc::clone(&pool.shared);
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let submitter = {
+            let (shared, runs) = (Arc::clone(&shared), Arc::clone(&runs));
+            thread::spawn(move || {
+                let job = move || {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                };
+                shared.submit(Priority::Normal, job, None, None, None, RejectionPolicy::Abort).is_ok()
+            })
+        };
+
+        drop(pool);
+        assert_eq!(shared.live_workers.load(Ordering::SeqCst), 0, "worker still counted as live");
+        assert!(lock(&shared.workers).is_empty(), "worker left unjoined");
+
+        let accepted = submitter.join().unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), usize::from(accepted), "accepted job lost or run twice");
+    });
+}
+
+#[test]
+fn lock_free_pool_drop_races_execute() {
+    check_drop_races_execute(Scheduler::LockFree);
+}
+
+#[test]
+fn channel_pool_drop_races_execute() {
+    check_drop_races_execute(Scheduler::Channel);
+}
+
+#[test]
+fn stealing_pool_drop_races_execute() {
+    check_drop_races_execute(Scheduler::WorkStealing);
+}
+
+#[test]
+fn pending_wait_sees_last_finish() {
+    model(|| {
+        let pending = Arc::new(Pending::default());
+        pending.add();
+        pending.add();
+
+        let finishers: Vec<_> = (0..2)
+            .map(|_| {
+                let pending = Arc::clone(&pending);
+                thread::spawn(move || pending.finish())
+            })
+            .collect();
+
+        assert!(pending.wait(None));
+        assert_eq!(pending.count(), 0);
+        for finisher in finishers {
+            finisher.join().unwrap();
+        }
+    });
+}
+
+#[test]
+fn keyed_lane_runs_jobs_one_at_a_time() {
+    /// Claim the lane for a job, then run it and every job passed on to this thread, as
+    /// `ThreadPool::execute_keyed` and the workers do.
+    fn submit(lanes: &KeyedLanes, running: &AtomicBool, runs: &Arc<AtomicUsize>) {
+        let job = lanes.claim(0, counted(runs), Priority::Normal, |job| job);
+        let mut next = job.map(|job| (job, Priority::Normal));
+        while let Some((job, _)) = next {
+            assert!(!running.swap(true, Ordering::SeqCst), "two jobs in a lane ran at once");
+            (job.job)();
+            running.store(false, Ordering::SeqCst);
+            next = lanes.release(0);
+        }
+    }
+
+    model(|| {
+        let lanes = Arc::new(KeyedLanes::new());
+        let running = Arc::new(AtomicBool::new(false));
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let other = {
+            let (lanes, running, runs) = (Arc::clone(&lanes), Arc::clone(&running), Arc::clone(&runs));
+            thread::spawn(move || submit(&lanes, &running, &runs))
+        };
+        submit(&lanes, &running, &runs);
+        other.join().unwrap();
+
+        assert_eq!(runs.load(Ordering::SeqCst), 2, "a job in the lane was lost");
+        assert!(lanes.drain().is_empty());
+    });
+}
+
+#[test]
+fn unbounded_queue_passes_each_value_on_once() {
+    model(|| {
+        // Fill most of the first block, so the pushes below cross into the next.
+        let queue = Arc::new(Unbounded::new());
+        queue.push(0);
+        queue.push(1);
+
+        let pushers: Vec<_> = (2..4)
+            .map(|value| {
+                let queue = Arc::clone(&queue);
+                thread::spawn(move || queue.push(value))
+            })
+            .collect();
+        let popper = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || (0..2).filter_map(|_| queue.pop()).collect::<Vec<_>>())
+        };
+
+        for pusher in pushers {
+            pusher.join().unwrap();
+        }
+        let mut values = popper.join().unwrap();
+        values.extend(std::iter::from_fn(|| queue.pop()));
+        values.sort_unstable();
+        assert_eq!(values, [0, 1, 2, 3], "a value was lost or popped twice");
+    });
+}
+
+#[test]
+fn bounded_queue_passes_each_value_on_once() {
+    model(|| {
+        let queue = Arc::new(Bounded::new(1));
+        queue.push(0).unwrap();
+
+        // The push only fits once the pop has made room.
+        let pusher = {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || queue.push(1).is_ok())
+        };
+        let popped = queue.pop();
+        let pushed = pusher.join().unwrap();
+
+        assert_eq!(popped, Some(0));
+        assert_eq!(queue.pop(), pushed.then_some(1));
+        assert_eq!(queue.len(), 0);
+    });
+}
@@ -0,0 +1,682 @@
+// The queues in this file are adapted from crossbeam-queue 0.3.12: `Bounded` from
+// `ArrayQueue` and `Unbounded` from `SegQueue`, with `Backoff` and `CachePadded` cut down
+// from crossbeam-utils 0.8. They have been changed to build on `crate::sync`, so they can be
+// checked under loom, and to drop what the pool doesn't use.
+//
+// Source: https://github.com/crossbeam-rs/crossbeam/tree/crossbeam-queue-0.3.12/crossbeam-queue/src
+//
+// Copyright (c) 2019 The Crossbeam Project Developers
+//
+// Licensed under either of the Apache License, Version 2.0 or the MIT license, at your
+// option. Both are reproduced in full in LICENSE-crossbeam at the root of this repository.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this
+// software and associated documentation files (the "Software"), to deal in the Software
+// without restriction, including without limitation the rights to use, copy, modify,
+// merge, publish, distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to the following
+// conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies
+// or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED,
+// INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A
+// PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT
+// HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF
+// CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR
+// THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+use std::{array, hint, mem::MaybeUninit, ops::Deref, ptr, sync::atomic::Ordering};
+
+use crate::sync::{fence, yield_now, AtomicPtr, AtomicUsize, UnsafeCell};
+
+/// How many times `Backoff` doubles its spinning before it starts yielding the thread
+/// instead.
+const SPIN_LIMIT: u32 = 6;
+
+/// Set in an `Unbounded` slot's state once its value has been written.
+const WRITE: usize = 1;
+/// Set in an `Unbounded` slot's state once its value has been read.
+const READ: usize = 2;
+/// Set in an `Unbounded` slot's state when the block should be freed by whoever reads it.
+const DESTROY: usize = 4;
+
+/// The number of indices per block of an `Unbounded` queue. The last index of each block
+/// has no slot, and marks that the next block is being installed. Loom models are run with
+/// small blocks, so that the models cross from one block to the next.
+#[cfg(not(loom))]
+const LAP: usize = 32;
+#[cfg(loom)]
+const LAP: usize = 4;
+
+/// The number of slots in a block of an `Unbounded` queue.
+const BLOCK_CAP: usize = LAP - 1;
+
+/// How far an `Unbounded` queue's indices are shifted left, to make room for `HAS_NEXT`.
+const SHIFT: usize = 1;
+
+/// Set in an `Unbounded` queue's head index when the head block is known not to be the
+/// last, so a pop doesn't need to check the tail to know it isn't empty.
+const HAS_NEXT: usize = 1;
+
+/// Pads and aligns a value to the length of a cache line, so that the head and tail of a
+/// queue, which are written by different threads, don't share one.
+#[repr(align(128))]
+struct CachePadded<T>(T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// Spins, and then yields, while a thread waits for another to make progress.
+/// 
+/// ## Fields
+/// - `step`: How many times the thread has backed off so far.
+struct Backoff {
+    step: u32,
+}
+
+impl Backoff {
+    fn new() -> Backoff {
+        Backoff { step: 0 }
+    }
+
+    /// Back off after losing a race to another thread, which will have made progress.
+    fn spin(&mut self) {
+        if cfg!(loom) {
+            yield_now();
+            return;
+        }
+
+        for _ in 0..1 << self.step.min(SPIN_LIMIT) {
+            hint::spin_loop();
+        }
+        self.step = (self.step + 1).min(SPIN_LIMIT);
+    }
+
+    /// Back off while waiting for another thread to finish what it has started, yielding
+    /// the thread once it has waited a while.
+    fn snooze(&mut self) {
+        if cfg!(loom) || self.step >= SPIN_LIMIT {
+            yield_now();
+            return;
+        }
+
+        for _ in 0..1 << self.step {
+            hint::spin_loop();
+        }
+        self.step += 1;
+    }
+}
+
+/// A lock-free queue that any number of threads push to and pop from, first in, first out.
+/// 
+/// No locks are taken. A pop may spin for a moment on a push that has claimed its slot but
+/// not yet written it, which is as long as the pusher takes to copy the value in.
+/// 
+/// ## Variants
+/// - `Bounded`: A queue that holds at most a fixed number of values.
+/// - `Unbounded`: A queue that grows as values are pushed.
+pub(crate) enum Queue<T> {
+    Bounded(Bounded<T>),
+    Unbounded(Unbounded<T>),
+}
+
+impl<T> Queue<T> {
+    /// Create an empty queue.
+    /// 
+    /// ## Parameters
+    /// - `capacity`: The most values the queue holds, or `None` for no limit.
+    /// 
+    /// ## Panics
+    /// If `capacity` is zero.
+    pub(crate) fn new(capacity: Option<usize>) -> Queue<T> {
+        match capacity {
+            Some(capacity) => Queue::Bounded(Bounded::new(capacity)),
+            None => Queue::Unbounded(Unbounded::new()),
+        }
+    }
+
+    /// Add a value to the back of the queue.
+    /// 
+    /// ## Returns
+    /// `Ok(())` if the value was added, or the value back if the queue is bounded and full.
+    pub(crate) fn push(&self, value: T) -> Result<(), T> {
+        match self {
+            Queue::Bounded(queue) => queue.push(value),
+            Queue::Unbounded(queue) => {
+                queue.push(value);
+                Ok(())
+            },
+        }
+    }
+
+    /// Take the value at the front of the queue, if there is one.
+    pub(crate) fn pop(&self) -> Option<T> {
+        match self {
+            Queue::Bounded(queue) => queue.pop(),
+            Queue::Unbounded(queue) => queue.pop(),
+        }
+    }
+
+    /// The number of values in the queue. Pushes and pops running at the same time may or
+    /// may not be counted.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Queue::Bounded(queue) => queue.len(),
+            Queue::Unbounded(queue) => queue.len(),
+        }
+    }
+
+    /// Whether the queue is empty, with the same caveat as `len`.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A slot in a `Bounded` queue.
+/// 
+/// ## Fields
+/// - `stamp`: The index the slot is next to be pushed to at, or one past the index it was
+///   pushed to at if it holds a value.
+/// - `value`: The value, if it holds one.
+struct BoundedSlot<T> {
+    stamp: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// A lock-free queue over a ring buffer of a fixed size.
+/// 
+/// An index is a position in the buffer plus a lap count, the lap being a power of two
+/// at least one more than the capacity. Each slot's stamp says which lap it is ready to be
+/// pushed to or popped from in, so a push or pop claims a slot by advancing the tail or
+/// head past it once its stamp is right.
+/// 
+/// ## Fields
+/// - `head`: The index of the next value to pop.
+/// - `tail`: The index the next value is pushed at.
+/// - `slots`: The buffer.
+/// - `one_lap`: The amount one lap adds to an index.
+pub(crate) struct Bounded<T> {
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+    slots: Box<[BoundedSlot<T>]>,
+    one_lap: usize,
+}
+
+// Values only ever move from the thread pushing them to the thread popping them.
+unsafe impl<T: Send> Send for Bounded<T> {}
+unsafe impl<T: Send> Sync for Bounded<T> {}
+
+impl<T> Bounded<T> {
+    /// Create an empty queue.
+    /// 
+    /// ## Parameters
+    /// - `capacity`: The most values the queue holds.
+    /// 
+    /// ## Panics
+    /// If `capacity` is zero.
+    pub(crate) fn new(capacity: usize) -> Bounded<T> {
+        assert!(capacity > 0, "a bounded queue must have room for a value");
+
+        let slots = (0..capacity)
+            .map(|index| BoundedSlot {
+                stamp: AtomicUsize::new(index),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+
+        Bounded {
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+            slots,
+            one_lap: (capacity + 1).next_power_of_two(),
+        }
+    }
+
+    /// The index after `index`, moving on to the next lap at the end of the buffer.
+    fn next(&self, index: usize) -> usize {
+        if (index & (self.one_lap - 1)) + 1 < self.slots.len() {
+            index + 1
+        } else {
+            (index & !(self.one_lap - 1)).wrapping_add(self.one_lap)
+        }
+    }
+
+    /// Add a value to the back of the queue.
+    /// 
+    /// ## Returns
+    /// `Ok(())` if the value was added, or the value back if the queue is full.
+    pub(crate) fn push(&self, value: T) -> Result<(), T> {
+        let mut backoff = Backoff::new();
+        let mut tail = self.tail.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.slots[tail & (self.one_lap - 1)];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+
+            if stamp == tail {
+                // The slot is free in this lap, so try to claim it.
+                match self.tail.compare_exchange_weak(tail, self.next(tail), Ordering::SeqCst, Ordering::Relaxed) {
+                    Ok(_) => {
+                        // SAFETY: Claiming the slot gives this thread sole access to it until
+                        // the stamp is moved on.
+                        slot.value.with_mut(|slot| unsafe { slot.write(MaybeUninit::new(value)) });
+                        slot.stamp.store(tail + 1, Ordering::Release);
+                        return Ok(());
+                    },
+                    Err(current) => {
+                        tail = current;
+                        backoff.spin();
+                    },
+                }
+            } else if stamp.wrapping_add(self.one_lap) == tail + 1 {
+                // The slot still holds the value pushed a lap ago, so the queue may be full.
+                fence(Ordering::SeqCst);
+                if self.head.load(Ordering::Relaxed).wrapping_add(self.one_lap) == tail {
+                    return Err(value);
+                }
+
+                backoff.spin();
+                tail = self.tail.load(Ordering::Relaxed);
+            } else {
+                // Another push has claimed the slot and not finished, or the tail has moved on.
+                backoff.snooze();
+                tail = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Take the value at the front of the queue, if there is one.
+    pub(crate) fn pop(&self) -> Option<T> {
+        let mut backoff = Backoff::new();
+        let mut head = self.head.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.slots[head & (self.one_lap - 1)];
+            let stamp = slot.stamp.load(Ordering::Acquire);
+
+            if stamp == head + 1 {
+                // The slot holds a value pushed in this lap, so try to claim it.
+                match self.head.compare_exchange_weak(head, self.next(head), Ordering::SeqCst, Ordering::Relaxed) {
+                    Ok(_) => {
+                        // SAFETY: The stamp says the value was written, and claiming the slot
+                        // gives this thread sole access to it until the stamp is moved on.
+                        let value = slot.value.with_mut(|slot| unsafe { slot.read().assume_init() });
+                        slot.stamp.store(head.wrapping_add(self.one_lap), Ordering::Release);
+                        return Some(value);
+                    },
+                    Err(current) => {
+                        head = current;
+                        backoff.spin();
+                    },
+                }
+            } else if stamp == head {
+                // The slot hasn't been pushed to in this lap, so the queue may be empty.
+                fence(Ordering::SeqCst);
+                if self.tail.load(Ordering::Relaxed) == head {
+                    return None;
+                }
+
+                backoff.spin();
+                head = self.head.load(Ordering::Relaxed);
+            } else {
+                // Another pop has claimed the slot and not finished, or the head has moved on.
+                backoff.snooze();
+                head = self.head.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// The number of values in the queue.
+    pub(crate) fn len(&self) -> usize {
+        loop {
+            // Read the tail either side of the head, so the two are from the same moment.
+            let tail = self.tail.load(Ordering::SeqCst);
+            let head = self.head.load(Ordering::SeqCst);
+            if self.tail.load(Ordering::SeqCst) == tail {
+                return self.distance(head, tail);
+            }
+        }
+    }
+
+    /// The number of values between the head and tail indices.
+    fn distance(&self, head: usize, tail: usize) -> usize {
+        let head_index = head & (self.one_lap - 1);
+        let tail_index = tail & (self.one_lap - 1);
+
+        if head_index < tail_index {
+            tail_index - head_index
+        } else if head_index > tail_index {
+            self.slots.len() - head_index + tail_index
+        } else if head == tail {
+            0
+        } else {
+            self.slots.len()
+        }
+    }
+}
+
+impl<T> Drop for Bounded<T> {
+    fn drop(&mut self) {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head_index = head & (self.one_lap - 1);
+
+        for offset in 0..self.distance(head, tail) {
+            let slot = &self.slots[(head_index + offset) % self.slots.len()];
+            // SAFETY: Every slot between the head and the tail holds a value, and nothing
+            // else can access the queue while it is dropped.
+            slot.value.with_mut(|slot| unsafe { (*slot).assume_init_drop() });
+        }
+    }
+}
+
+/// A slot in a block of an `Unbounded` queue.
+/// 
+/// ## Fields
+/// - `value`: The value, once it has been written.
+/// - `state`: The `WRITE`, `READ` and `DESTROY` flags.
+struct UnboundedSlot<T> {
+    value: UnsafeCell<MaybeUninit<T>>,
+    state: AtomicUsize,
+}
+
+impl<T> UnboundedSlot<T> {
+    /// Wait until the push that claimed the slot has written its value.
+    fn wait_write(&self) {
+        let mut backoff = Backoff::new();
+        while self.state.load(Ordering::Acquire) & WRITE == 0 {
+            backoff.snooze();
+        }
+    }
+}
+
+/// A block of slots in an `Unbounded` queue, linked to the next block once the queue
+/// outgrows it.
+/// 
+/// ## Fields
+/// - `next`: The next block, once it has been installed.
+/// - `slots`: The slots.
+struct Block<T> {
+    next: AtomicPtr<Block<T>>,
+    slots: [UnboundedSlot<T>; BLOCK_CAP],
+}
+
+impl<T> Block<T> {
+    fn new() -> Box<Block<T>> {
+        Box::new(Block {
+            next: AtomicPtr::new(ptr::null_mut()),
+            slots: array::from_fn(|_| UnboundedSlot {
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+                state: AtomicUsize::new(0),
+            }),
+        })
+    }
+
+    /// Wait until the push that filled the block has installed the next one.
+    fn wait_next(&self) -> *mut Block<T> {
+        let mut backoff = Backoff::new();
+        loop {
+            let next = self.next.load(Ordering::Acquire);
+            if !next.is_null() {
+                return next;
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Free a block whose slots have all been claimed by pops, once the last of them has
+    /// read its value. A pop still reading from a slot at or after `start` is left to free
+    /// the block itself.
+    /// 
+    /// ## Safety
+    /// Every slot before `start` must have been read, and the caller must own the block's
+    /// last slot.
+    unsafe fn destroy(this: *mut Block<T>, start: usize) {
+        // The last slot is never flagged: its pop is the one that starts destroying the block.
+        for index in start..BLOCK_CAP - 1 {
+            let slot = unsafe { &(*this).slots[index] };
+            if slot.state.load(Ordering::Acquire) & READ == 0 && slot.state.fetch_or(DESTROY, Ordering::AcqRel) & READ == 0 {
+                return;
+            }
+        }
+
+        drop(unsafe { Box::from_raw(this) });
+    }
+}
+
+/// The head or tail of an `Unbounded` queue.
+/// 
+/// ## Fields
+/// - `index`: The index of the next value to pop or push, shifted left by `SHIFT`.
+/// - `block`: The block the index is in.
+struct Position<T> {
+    index: AtomicUsize,
+    block: AtomicPtr<Block<T>>,
+}
+
+/// A lock-free queue over a linked list of blocks of slots, which grows a block at a time.
+/// 
+/// A push claims a slot by advancing the tail, and the push that claims a block's last slot
+/// installs the next block. A pop claims a slot by advancing the head, and the blocks are
+/// freed by whichever pop is the last to finish reading from them.
+/// 
+/// ## Fields
+/// - `head`: Where the next value is popped from.
+/// - `tail`: Where the next value is pushed to.
+pub(crate) struct Unbounded<T> {
+    head: CachePadded<Position<T>>,
+    tail: CachePadded<Position<T>>,
+}
+
+// Values only ever move from the thread pushing them to the thread popping them.
+unsafe impl<T: Send> Send for Unbounded<T> {}
+unsafe impl<T: Send> Sync for Unbounded<T> {}
+
+impl<T> Unbounded<T> {
+    /// Create an empty queue.
+    pub(crate) fn new() -> Unbounded<T> {
+        let block = Box::into_raw(Block::new());
+        Unbounded {
+            head: CachePadded(Position {
+                index: AtomicUsize::new(0),
+                block: AtomicPtr::new(block),
+            }),
+            tail: CachePadded(Position {
+                index: AtomicUsize::new(0),
+                block: AtomicPtr::new(block),
+            }),
+        }
+    }
+
+    /// Add a value to the back of the queue.
+    pub(crate) fn push(&self, value: T) {
+        let mut backoff = Backoff::new();
+        let mut tail = self.tail.index.load(Ordering::Acquire);
+        let mut block = self.tail.block.load(Ordering::Acquire);
+        let mut next_block = None;
+
+        loop {
+            let offset = (tail >> SHIFT) % LAP;
+
+            // The next block is being installed.
+            if offset == BLOCK_CAP {
+                backoff.snooze();
+                tail = self.tail.index.load(Ordering::Acquire);
+                block = self.tail.block.load(Ordering::Acquire);
+                continue;
+            }
+
+            // Allocate the next block before claiming the last slot, so the other pushes don't
+            // wait on the allocation.
+            if offset + 1 == BLOCK_CAP && next_block.is_none() {
+                next_block = Some(Block::new());
+            }
+
+            let new_tail = tail + (1 << SHIFT);
+            match self.tail.index.compare_exchange_weak(tail, new_tail, Ordering::SeqCst, Ordering::Acquire) {
+                Ok(_) => {
+                    // SAFETY: The block can't be freed before the slot just claimed in it has
+                    // been popped, so it outlives this push.
+                    let block = unsafe { &*block };
+
+                    if offset + 1 == BLOCK_CAP {
+                        let next_block = Box::into_raw(next_block.expect("allocated before claiming the last slot"));
+                        self.tail.block.store(next_block, Ordering::Release);
+                        self.tail.index.fetch_add(1 << SHIFT, Ordering::Release);
+                        block.next.store(next_block, Ordering::Release);
+                    }
+
+                    let slot = &block.slots[offset];
+                    // SAFETY: Claiming the slot gives this thread sole access to it until the
+                    // `WRITE` flag is set.
+                    slot.value.with_mut(|slot| unsafe { slot.write(MaybeUninit::new(value)) });
+                    slot.state.fetch_or(WRITE, Ordering::Release);
+                    return;
+                },
+                Err(current) => {
+                    tail = current;
+                    block = self.tail.block.load(Ordering::Acquire);
+                    backoff.spin();
+                },
+            }
+        }
+    }
+
+    /// Take the value at the front of the queue, if there is one.
+    pub(crate) fn pop(&self) -> Option<T> {
+        let mut backoff = Backoff::new();
+        let mut head = self.head.index.load(Ordering::Acquire);
+        let mut block = self.head.block.load(Ordering::Acquire);
+
+        loop {
+            let offset = (head >> SHIFT) % LAP;
+
+            // The head is moving on to the next block.
+            if offset == BLOCK_CAP {
+                backoff.snooze();
+                head = self.head.index.load(Ordering::Acquire);
+                block = self.head.block.load(Ordering::Acquire);
+                continue;
+            }
+
+            let mut new_head = head + (1 << SHIFT);
+            if new_head & HAS_NEXT == 0 {
+                fence(Ordering::SeqCst);
+                let tail = self.tail.index.load(Ordering::Relaxed);
+
+                if head >> SHIFT == tail >> SHIFT {
+                    return None;
+                }
+                if (head >> SHIFT) / LAP != (tail >> SHIFT) / LAP {
+                    new_head |= HAS_NEXT;
+                }
+            }
+
+            match self.head.index.compare_exchange_weak(head, new_head, Ordering::SeqCst, Ordering::Acquire) {
+                Ok(_) => {
+                    // SAFETY: The block can't be freed before the slot just claimed in it has
+                    // been read, which this pop has yet to do.
+                    let current = unsafe { &*block };
+
+                    if offset + 1 == BLOCK_CAP {
+                        let next = current.wait_next();
+                        let mut next_index = (new_head & !HAS_NEXT).wrapping_add(1 << SHIFT);
+                        // SAFETY: The next block is only freed once the head has moved past it.
+                        if !unsafe { &*next }.next.load(Ordering::Relaxed).is_null() {
+                            next_index |= HAS_NEXT;
+                        }
+
+                        self.head.block.store(next, Ordering::Release);
+                        self.head.index.store(next_index, Ordering::Release);
+                    }
+
+                    let slot = &current.slots[offset];
+                    slot.wait_write();
+                    // SAFETY: The value has been written, and claiming the slot gives this
+                    // thread sole access to it.
+                    let value = slot.value.with_mut(|slot| unsafe { slot.read().assume_init() });
+
+                    // Free the block if this was its last slot, or if a pop of its last slot
+                    // found this one still being read.
+                    if offset + 1 == BLOCK_CAP {
+                        // SAFETY: This pop owns the last slot, and every earlier slot has been
+                        // claimed.
+                        unsafe { Block::destroy(block, 0) };
+                    } else if slot.state.fetch_or(READ, Ordering::AcqRel) & DESTROY != 0 {
+                        // SAFETY: The pop of the last slot has checked every slot before this one.
+                        unsafe { Block::destroy(block, offset + 1) };
+                    }
+                    return Some(value);
+                },
+                Err(current) => {
+                    head = current;
+                    block = self.head.block.load(Ordering::Acquire);
+                    backoff.spin();
+                },
+            }
+        }
+    }
+
+    /// The number of values in the queue.
+    pub(crate) fn len(&self) -> usize {
+        loop {
+            // Read the tail either side of the head, so the two are from the same moment.
+            let tail = self.tail.index.load(Ordering::SeqCst);
+            let head = self.head.index.load(Ordering::SeqCst);
+            if self.tail.index.load(Ordering::SeqCst) != tail {
+                continue;
+            }
+
+            let mut tail = tail >> SHIFT;
+            let mut head = head >> SHIFT;
+
+            // An index on the end of a block is really at the start of the next one.
+            if tail % LAP == BLOCK_CAP {
+                tail += 1;
+            }
+            if head % LAP == BLOCK_CAP {
+                head += 1;
+            }
+
+            // Count from the start of the head's block, leaving out the index with no slot at
+            // the end of each block the tail has passed.
+            let start = head / LAP * LAP;
+            let (head, tail) = (head - start, tail - start);
+            return tail - head - tail / LAP;
+        }
+    }
+}
+
+impl<T> Drop for Unbounded<T> {
+    fn drop(&mut self) {
+        let mut head = self.head.index.load(Ordering::Relaxed) >> SHIFT;
+        let tail = self.tail.index.load(Ordering::Relaxed) >> SHIFT;
+        let mut block = self.head.block.load(Ordering::Relaxed);
+
+        // SAFETY: Nothing else can access the queue while it is dropped, every slot between the
+        // head and the tail holds a value, and every block from the head's on is still allocated.
+        unsafe {
+            while head != tail {
+                let offset = head % LAP;
+                if offset < BLOCK_CAP {
+                    (*block).slots[offset].value.with_mut(|slot| (*slot).assume_init_drop());
+                } else {
+                    let next = (*block).next.load(Ordering::Relaxed);
+                    drop(Box::from_raw(block));
+                    block = next;
+                }
+                head += 1;
+            }
+
+            drop(Box::from_raw(block));
+        }
+    }
+}
+
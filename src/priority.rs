@@ -0,0 +1,135 @@
+use std::{collections::VecDeque, time::{Duration, Instant}};
+
+use crate::queue::QueuedJob;
+
+/// How long a job waits in the queue before it is treated as one priority level higher,
+/// so that lower-priority jobs are never starved by a steady stream of higher ones.
+pub(crate) const DEFAULT_AGING: Duration = Duration::from_millis(500);
+
+/// How urgently a job should run.
+/// 
+/// ## Variants
+/// - `High`: Jobs that must not wait behind ordinary work, such as health checks.
+/// - `Normal`: Ordinary jobs. This is what `ThreadPool::execute` uses.
+/// - `Low`: Background jobs that can wait until the pool is quiet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl Priority {
+    /// Every priority, from highest to lowest.
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    /// The index of the priority's lane, `0` being the highest.
+    pub(crate) fn lane(self) -> usize {
+        self as usize
+    }
+}
+
+/// The number of jobs waiting in a pool's queue at each priority.
+/// 
+/// ## Fields
+/// - `high`: The number of `Priority::High` jobs waiting.
+/// - `normal`: The number of `Priority::Normal` jobs waiting.
+/// - `low`: The number of `Priority::Low` jobs waiting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueDepths {
+    pub high: usize,
+    pub normal: usize,
+    pub low: usize,
+}
+
+impl QueueDepths {
+    /// The number of jobs waiting at `priority`.
+    pub fn get(&self, priority: Priority) -> usize {
+        match priority {
+            Priority::High => self.high,
+            Priority::Normal => self.normal,
+            Priority::Low => self.low,
+        }
+    }
+
+    /// The number of jobs waiting at every priority.
+    pub fn total(&self) -> usize {
+        self.high + self.normal + self.low
+    }
+}
+
+/// Queued jobs, with a first in, first out lane for each priority.
+/// 
+/// ## Fields
+/// - `lanes`: The jobs at each priority, highest first.
+/// - `aging`: How long a job waits before it is treated as one priority level higher.
+pub(crate) struct Lanes {
+    lanes: [VecDeque<QueuedJob>; 3],
+    aging: Duration,
+}
+
+impl Lanes {
+    /// Create empty lanes.
+    /// 
+    /// ## Parameters
+    /// - `aging`: How long a job waits before it is treated as one priority level higher.
+    pub(crate) fn new(aging: Duration) -> Lanes {
+        Lanes {
+            lanes: Default::default(),
+            aging,
+        }
+    }
+
+    /// Add a job to the back of its priority's lane.
+    pub(crate) fn push(&mut self, job: QueuedJob, priority: Priority) {
+        self.lanes[priority.lane()].push_back(job);
+    }
+
+    /// Take the job that should run next.
+    /// 
+    /// Each lane's oldest job is promoted one level for every `aging` it has waited, and the
+    /// highest of those runs, the one that has waited longest winning a tie.
+    pub(crate) fn pop(&mut self) -> Option<QueuedJob> {
+        let now = Instant::now();
+        let aging = self.aging.as_nanos().max(1);
+
+        let (_, _, lane) = self
+            .lanes
+            .iter()
+            .enumerate()
+            .filter_map(|(lane, jobs)| {
+                let queued_at = jobs.front()?.queued_at;
+                let promotions = now.saturating_duration_since(queued_at).as_nanos() / aging;
+                let effective = lane.saturating_sub(promotions.try_into().unwrap_or(usize::MAX));
+                Some((effective, queued_at, lane))
+            })
+            .min()?;
+
+        self.lanes[lane].pop_front()
+    }
+
+    /// Take the job that has waited longest in the lowest-priority lane that has any.
+    pub(crate) fn pop_oldest(&mut self) -> Option<QueuedJob> {
+        self.lanes.iter_mut().rev().find_map(VecDeque::pop_front)
+    }
+
+    /// Take every job, in the order they would have run.
+    pub(crate) fn drain(&mut self) -> Vec<QueuedJob> {
+        std::iter::from_fn(|| self.pop()).collect()
+    }
+
+    /// The number of jobs in every lane.
+    pub(crate) fn len(&self) -> usize {
+        self.lanes.iter().map(VecDeque::len).sum()
+    }
+
+    /// The number of jobs in each lane.
+    pub(crate) fn depths(&self) -> QueueDepths {
+        QueueDepths {
+            high: self.lanes[Priority::High.lane()].len(),
+            normal: self.lanes[Priority::Normal.lane()].len(),
+            low: self.lanes[Priority::Low.lane()].len(),
+        }
+    }
+}
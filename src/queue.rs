@@ -0,0 +1,369 @@
+use std::{sync::{atomic::Ordering, mpsc::RecvTimeoutError, PoisonError}, time::{Duration, Instant}};
+
+use crate::{cancel::CancellationToken, lockfree::LockFreeQueue, priority::{Lanes, Priority, QueueDepths}, shared::lock, steal::StealingQueue, sync::{AtomicBool, Condvar, Mutex}, watchdog::Deadline, Job};
+
+/// What a bounded `ThreadPool` does with a new job when its queue is full.
+/// 
+/// ## Variants
+/// - `Block`: Block the caller until a worker frees up space in the queue.
+/// - `Abort`: Fail fast, handing the job back in `ExecuteError::QueueFull`.
+/// - `CallerRuns`: Run the job on the calling thread, which also slows the caller down.
+/// - `DiscardOldest`: Drop the lowest-priority job that has been queued the longest to make room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectionPolicy {
+    Block,
+    Abort,
+    CallerRuns,
+    DiscardOldest,
+}
+
+/// How a `ThreadPool` hands jobs to its workers.
+/// 
+/// ## Variants
+/// - `LockFree`: The default. One lock-free queue per priority, shared by every worker, so
+///   submitting and taking jobs never waits on a lock. Jobs of the same priority run first
+///   in, first out. Aging can't see when the job at the front of a priority was queued, so a
+///   priority is promoted by how long ago a job was last taken from it, or how long ago it
+///   last went from empty to holding a job. A job is never promoted sooner than with
+///   `Channel`, but may be promoted later.
+/// - `Channel`: One queue shared by every worker, behind a single lock. Jobs of the same
+///   priority run strictly first in, first out.
+/// - `WorkStealing`: A deque per worker, with idle workers stealing from busy ones. Jobs
+///   submitted by a running job stay on that worker's deque, and workers take jobs
+///   submitted from outside the pool in batches, so there is much less contention when
+///   there are many small jobs. Jobs are no longer run in the order they were submitted,
+///   and priorities only apply to jobs submitted from outside the pool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scheduler {
+    #[default]
+    LockFree,
+    Channel,
+    WorkStealing,
+}
+
+/// Something that can be queued as a job.
+/// 
+/// The pool holds on to it unboxed until it is sure to queue it, so that a rejected job can be
+/// handed back as it was given.
+pub(crate) trait IntoJob: Send + 'static {
+    /// Box it up as a job.
+    fn into_job(self) -> Job;
+}
+
+impl<F> IntoJob for F
+where
+    F: FnOnce() + Send + 'static
+{
+    fn into_job(self) -> Job {
+        Box::new(self)
+    }
+}
+
+/// A job waiting in the queue.
+/// 
+/// ## Fields
+/// - `job`: The job.
+/// - `token`: The token that cancels the job if it is cancelled before the job runs, if any.
+/// - `deadline`: How long the job may run before the watchdog reports it, if it is watched.
+/// - `lane`: The lane of a job executed with a key, which is passed on once the job is done.
+/// - `queued_at`: When the job was queued.
+pub(crate) struct QueuedJob {
+    pub(crate) job: Job,
+    pub(crate) token: Option<CancellationToken>,
+    pub(crate) deadline: Option<Deadline>,
+    pub(crate) lane: Option<u64>,
+    pub(crate) queued_at: Instant,
+}
+
+impl QueuedJob {
+    /// Wrap a job that is about to be queued.
+    pub(crate) fn new(job: Job, token: Option<CancellationToken>, deadline: Option<Deadline>, lane: Option<u64>) -> QueuedJob {
+        QueuedJob {
+            job,
+            token,
+            deadline,
+            lane,
+            queued_at: Instant::now(),
+        }
+    }
+
+    /// Whether the job's token has been cancelled.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.token.as_ref().is_some_and(CancellationToken::is_cancelled)
+    }
+}
+
+/// A queue shared by every worker, guarded by a single lock.
+/// 
+/// ## Fields
+/// - `state`: The queued jobs, and whether the queue has been closed.
+/// - `capacity`: The most jobs the queue holds, if it is bounded.
+/// - `not_empty`: Signalled when a job is pushed or the queue is closed.
+/// - `not_full`: Signalled when a job is taken from a bounded queue.
+/// - `closed`: Whether the queue has been closed, readable without taking the lock.
+pub(crate) struct ChannelQueue {
+    state: Mutex<ChannelState>,
+    capacity: Option<usize>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    closed: AtomicBool,
+}
+
+/// The state of a `ChannelQueue`, guarded by its lock.
+/// 
+/// ## Fields
+/// - `lanes`: The queued jobs.
+/// - `closed`: Whether the pool has stopped sending jobs.
+struct ChannelState {
+    lanes: Lanes,
+    closed: bool,
+}
+
+impl ChannelQueue {
+    /// Create an empty ChannelQueue.
+    /// 
+    /// ## Parameters
+    /// - `capacity`: The most jobs the queue holds, or `None` for no limit.
+    /// - `aging`: How long a job waits before it is treated as one priority level higher.
+    pub(crate) fn new(capacity: Option<usize>, aging: Duration) -> ChannelQueue {
+        ChannelQueue {
+            state: Mutex::new(ChannelState {
+                lanes: Lanes::new(aging),
+                closed: false,
+            }),
+            capacity,
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Add a job to the queue, blocking while a bounded queue is full.
+    /// 
+    /// The job is refused if the queue has been closed, which is checked under the same lock
+    /// the workers take jobs under. A worker only sees the queue as disconnected once it is
+    /// closed and empty, so every job accepted here is run.
+    /// 
+    /// ## Parameters
+    /// - `f`: The job to add.
+    /// - `priority`: How urgently the job should run.
+    /// - `job`: Wraps the job up to be queued. Only called once the job has been accepted.
+    /// 
+    /// ## Returns
+    /// `Ok(())` if the job was queued, or the job back if the queue has been closed.
+    fn push<F>(&self, f: F, priority: Priority, job: impl FnOnce(F) -> QueuedJob) -> Result<(), F> {
+        let mut state = lock(&self.state);
+        if let Some(capacity) = self.capacity {
+            while state.lanes.len() >= capacity && !state.closed {
+                state = self.not_full.wait(state).unwrap_or_else(PoisonError::into_inner);
+            }
+        }
+        if state.closed {
+            return Err(f);
+        }
+
+        state.lanes.push(job(f), priority);
+        drop(state);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Add a job to the queue without waiting for room, even if the queue has been closed.
+    fn push_ready(&self, job: QueuedJob, priority: Priority) {
+        lock(&self.state).lanes.push(job, priority);
+        self.not_empty.notify_one();
+    }
+
+    /// Take the next job, waiting up to `timeout` for one to arrive.
+    fn pop(&self, timeout: Duration) -> Result<QueuedJob, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = lock(&self.state);
+
+        loop {
+            if let Some(job) = state.lanes.pop() {
+                drop(state);
+                self.not_full.notify_one();
+                return Ok(job);
+            }
+            if state.closed {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self.not_empty.wait_timeout(state, deadline - now).unwrap_or_else(PoisonError::into_inner).0;
+        }
+    }
+
+    /// Take the lowest-priority job that has been queued the longest.
+    fn pop_oldest(&self) -> Option<QueuedJob> {
+        let job = lock(&self.state).lanes.pop_oldest()?;
+        self.not_full.notify_one();
+        Some(job)
+    }
+
+    /// Take every queued job, in the order they would have run.
+    fn drain(&self) -> Vec<QueuedJob> {
+        let jobs = lock(&self.state).lanes.drain();
+        self.not_full.notify_all();
+        jobs
+    }
+
+    /// Stop accepting jobs and wake every waiting worker.
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        lock(&self.state).closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+}
+
+/// The pool's job queue, shared by the pool and its workers.
+/// 
+/// ## Variants
+/// - `LockFree`: One lock-free queue per priority shared by every worker. Boxed, as the
+///   queues' heads and tails are each padded to a cache line.
+/// - `Channel`: One queue shared by every worker, behind a single lock.
+/// - `WorkStealing`: A deque per worker, with idle workers stealing from busy ones.
+pub(crate) enum JobQueue {
+    LockFree(Box<LockFreeQueue>),
+    Channel(ChannelQueue),
+    WorkStealing(StealingQueue),
+}
+
+impl JobQueue {
+    /// Create an empty queue for the given `Scheduler`.
+    /// 
+    /// ## Parameters
+    /// - `scheduler`: How jobs are handed to the workers.
+    /// - `capacity`: The most jobs the queue holds, or `None` for no limit. Only the
+    ///   `LockFree` and `Channel` schedulers support a bounded queue.
+    /// - `aging`: How long a job waits before it is treated as one priority level higher.
+    pub(crate) fn new(scheduler: Scheduler, capacity: Option<usize>, aging: Duration) -> JobQueue {
+        match scheduler {
+            Scheduler::LockFree => JobQueue::LockFree(Box::new(LockFreeQueue::new(capacity, aging))),
+            Scheduler::Channel => JobQueue::Channel(ChannelQueue::new(capacity, aging)),
+            Scheduler::WorkStealing => JobQueue::WorkStealing(StealingQueue::new(aging)),
+        }
+    }
+
+    /// Prepare the queue for a worker starting on the current thread.
+    /// 
+    /// ## Parameters
+    /// - `worker`: The id of the worker.
+    pub(crate) fn register(&self, worker: usize) {
+        if let JobQueue::WorkStealing(queue) = self {
+            queue.register(worker);
+        }
+    }
+
+    /// Tidy up after a worker exiting on the current thread.
+    /// 
+    /// ## Parameters
+    /// - `worker`: The id of the worker.
+    pub(crate) fn unregister(&self, worker: usize) {
+        if let JobQueue::WorkStealing(queue) = self {
+            queue.unregister(worker);
+        }
+    }
+
+    /// Add a job to the queue. On a bounded queue this blocks until there is room.
+    /// 
+    /// ## Parameters
+    /// - `f`: The job to add.
+    /// - `priority`: How urgently the job should run.
+    /// - `job`: Wraps the job up to be queued. Only called once the job has been accepted,
+    ///   so a refused job is handed back as it was given.
+    /// 
+    /// ## Returns
+    /// `Ok(())` if the job was queued, or the job back if the queue has been closed. Once
+    /// this has returned `Ok`, the job is run, or drained, before the workers exit.
+    pub(crate) fn push<F>(&self, f: F, priority: Priority, job: impl FnOnce(F) -> QueuedJob) -> Result<(), F> {
+        match self {
+            JobQueue::LockFree(queue) => queue.push(f, priority, job),
+            JobQueue::Channel(queue) => queue.push(f, priority, job),
+            JobQueue::WorkStealing(queue) => queue.push(f, priority, job),
+        }
+    }
+
+    /// Add a job to the queue without waiting for room, for a job that has already been let
+    /// into the pool and waited its turn elsewhere. The job is queued even if the queue has
+    /// been closed, so the jobs a worker passes on while the queue drains still run.
+    /// 
+    /// ## Parameters
+    /// - `job`: The job to add.
+    /// - `priority`: How urgently the job should run.
+    pub(crate) fn push_ready(&self, job: QueuedJob, priority: Priority) {
+        match self {
+            JobQueue::LockFree(queue) => queue.push_ready(job, priority),
+            JobQueue::Channel(queue) => queue.push_ready(job, priority),
+            JobQueue::WorkStealing(queue) => queue.push_ready(job, priority),
+        }
+    }
+
+    /// Take a job for a worker, waiting up to `timeout` for one to arrive.
+    /// 
+    /// ## Parameters
+    /// - `worker`: The id of the worker taking the job.
+    /// - `timeout`: How long to wait for a job.
+    /// 
+    /// ## Returns
+    /// The job, `RecvTimeoutError::Timeout` if none arrived in time, or
+    /// `RecvTimeoutError::Disconnected` once the pool has stopped sending jobs and the
+    /// queue is empty.
+    pub(crate) fn recv_timeout(&self, worker: usize, timeout: Duration) -> Result<QueuedJob, RecvTimeoutError> {
+        match self {
+            JobQueue::LockFree(queue) => queue.pop(timeout),
+            JobQueue::Channel(queue) => queue.pop(timeout),
+            JobQueue::WorkStealing(queue) => queue.pop(worker, timeout),
+        }
+    }
+
+    /// Take the lowest-priority job that has been queued the longest, without waiting.
+    pub(crate) fn pop_oldest(&self) -> Option<QueuedJob> {
+        match self {
+            JobQueue::LockFree(queue) => queue.pop_oldest(),
+            JobQueue::Channel(queue) => queue.pop_oldest(),
+            JobQueue::WorkStealing(queue) => queue.pop_oldest(),
+        }
+    }
+
+    /// Take every queued job without waiting, in roughly the order they would have run.
+    pub(crate) fn drain(&self) -> Vec<QueuedJob> {
+        match self {
+            JobQueue::LockFree(queue) => queue.drain(),
+            JobQueue::Channel(queue) => queue.drain(),
+            JobQueue::WorkStealing(queue) => queue.drain(),
+        }
+    }
+
+    /// The number of jobs waiting at each priority.
+    pub(crate) fn depths(&self) -> QueueDepths {
+        match self {
+            JobQueue::LockFree(queue) => queue.depths(),
+            JobQueue::Channel(queue) => lock(&queue.state).lanes.depths(),
+            JobQueue::WorkStealing(queue) => queue.depths(),
+        }
+    }
+
+    /// Stop accepting jobs. Workers finish the jobs already queued, then see the queue
+    /// as disconnected.
+    pub(crate) fn close(&self) {
+        match self {
+            JobQueue::LockFree(queue) => queue.close(),
+            JobQueue::Channel(queue) => queue.close(),
+            JobQueue::WorkStealing(queue) => queue.close(),
+        }
+    }
+
+    /// Whether the queue has been closed.
+    pub(crate) fn is_closed(&self) -> bool {
+        match self {
+            JobQueue::LockFree(queue) => queue.is_closed(),
+            JobQueue::Channel(queue) => queue.closed.load(Ordering::Acquire),
+            JobQueue::WorkStealing(queue) => queue.is_closed(),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::{any::Any, marker::PhantomData, mem, panic::{self, AssertUnwindSafe}, sync::Arc};
+
+use crate::{group::Pending, shared::lock, sync::Mutex, worker, Job, Priority, RejectionPolicy, ThreadPool};
+
+/// A scope for jobs that borrow from the stack, created by `ThreadPool::scope`.
+/// 
+/// Every job executed on the scope has finished by the time `ThreadPool::scope` returns,
+/// so jobs may borrow anything that outlives the call.
+/// 
+/// ## Fields
+/// - `pool`: The pool the jobs run on.
+/// - `state`: Tracks the scope's unfinished jobs and the first panic among them.
+/// - `scope`: Makes `'scope` invariant, like `std::thread::Scope`.
+/// - `env`: Makes `'env` invariant, like `std::thread::Scope`.
+pub struct Scope<'scope, 'env: 'scope> {
+    pool: &'scope ThreadPool,
+    state: Arc<ScopeState>,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+/// The bookkeeping shared between a `Scope` and its jobs.
+/// 
+/// ## Fields
+/// - `pending`: The jobs that haven't finished, or been dropped, yet.
+/// - `panic`: The payload of the first job to panic, if any has.
+struct ScopeState {
+    pending: Pending,
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+/// A job executed on a `Scope`, which tells the scope when it has finished.
+/// 
+/// The scope is told from `drop`, so a job the pool discards without running still counts
+/// as finished, and only once whatever it borrows has been dropped.
+/// 
+/// ## Fields
+/// - `f`: The job, until it has run.
+/// - `state`: The scope's bookkeeping.
+struct ScopedJob<'scope> {
+    f: Option<Box<dyn FnOnce() + Send + 'scope>>,
+    state: Arc<ScopeState>,
+}
+
+impl ScopedJob<'_> {
+    /// Run the job, recording its panic if it has one.
+    fn run(mut self) {
+        if let Some(f) = self.f.take() {
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(f)) {
+                lock(&self.state.panic).get_or_insert(payload);
+            }
+        }
+    }
+}
+
+impl Drop for ScopedJob<'_> {
+    fn drop(&mut self) {
+        // Drop the job, and everything it borrows, before the scope may return.
+        drop(self.f.take());
+
+        self.state.pending.finish();
+    }
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// Create a scope for jobs on `pool`.
+    pub(crate) fn new(pool: &'scope ThreadPool) -> Scope<'scope, 'env> {
+        Scope {
+            pool,
+            state: Arc::new(ScopeState {
+                pending: Pending::default(),
+                panic: Mutex::new(None),
+            }),
+            scope: PhantomData,
+            env: PhantomData,
+        }
+    }
+
+    /// Block until every job executed on the scope has finished.
+    /// 
+    /// ## Returns
+    /// The payload of the first job to panic, if any did.
+    pub(crate) fn wait(&self) -> Option<Box<dyn Any + Send>> {
+        self.state.pending.wait(None);
+        lock(&self.state.panic).take()
+    }
+
+    /// Execute a job on the pool that may borrow from outside the scope.
+    /// 
+    /// If the pool rejects the job, because it is shutting down or its queue is full, the
+    /// job runs on the calling thread instead. Called from one of the pool's workers, it
+    /// never waits for room in a full queue, whatever the pool's `RejectionPolicy`, and runs
+    /// the job straight away instead.
+    /// 
+    /// ## Parameters
+    /// - `f`: The job to execute. This must implement `FnOnce()`.
+    pub fn execute<F>(&'scope self, f: F)
+    where
+        F: FnOnce() + Send + 'scope
+    {
+        self.state.pending.add();
+
+        let job = ScopedJob {
+            f: Some(Box::new(f)),
+            state: Arc::clone(&self.state),
+        };
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || job.run());
+
+        // SAFETY: `ThreadPool::scope` doesn't return until every `ScopedJob` has been dropped,
+        // whether or not it ran, so nothing the job borrows is used after `'scope` ends.
+        let job = unsafe { mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(job) };
+
+        // A worker waiting for room in the queue may be the one that would have made it, so
+        // a worker doesn't wait.
+        let shared = &self.pool.shared;
+        let policy = match worker::current(shared) {
+            Some(_) => RejectionPolicy::Abort,
+            None => shared.policy,
+        };
+        if let Err(err) = shared.submit(Priority::Normal, job, None, None, None, policy) {
+            (err.into_inner())();
+        }
+    }
+}
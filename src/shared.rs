@@ -0,0 +1,529 @@
+use std::{any::Any, fmt, mem, panic::{self, AssertUnwindSafe}, sync::{atomic::Ordering, Arc, PoisonError}, thread, time::{Duration, Instant}};
+
+use crate::{builder::ThreadConfig, cancel::CancellationToken, keyed::KeyedLanes, logging::{self, Level, Logger}, queue::{IntoJob, JobQueue, QueuedJob}, stats::Metrics, sync::{mpsc::Sender, AtomicUsize, Condvar, Mutex, MutexGuard}, watchdog::{Deadline, Watchdog}, worker::{self, SupervisorEvent, Worker}, ExecuteError, Job, PanicHandler, PoolCreationError, Priority, RejectionPolicy};
+
+/// How often an idle worker in a pool without a keep-alive wakes up to check whether the
+/// pool has been resized below its current size.
+const IDLE_POLL: Duration = Duration::from_secs(1);
+
+/// How often a shutdown with a deadline checks whether the workers have finished.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(10);
+
+/// Lock a mutex, carrying on with the data if a thread panicked while holding it.
+/// 
+/// None of the pool's locks guard data that a panic can leave half-updated, so a poisoned
+/// lock is safe to use and shouldn't take the rest of the pool down with it.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// How many workers a pool runs.
+/// 
+/// ## Fields
+/// - `min_threads`: The number of workers started up front and always kept running.
+/// - `max_threads`: The most workers the pool will grow to when jobs back up.
+/// - `keep_alive`: How long a worker above `min_threads` may sit idle before it retires.
+///   `None` for a fixed-size pool.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Sizing {
+    pub(crate) min_threads: usize,
+    pub(crate) max_threads: usize,
+    pub(crate) keep_alive: Option<Duration>,
+}
+
+/// State shared between a `ThreadPool` and its workers.
+/// 
+/// ## Fields
+/// - `queue`: The job queue.
+/// - `capacity`: The most jobs the queue holds, if it is bounded.
+/// - `policy`: What to do with a job submitted while a bounded queue is full.
+/// - `live_workers`: The number of worker threads that are still running.
+/// - `busy_workers`: The number of workers currently running a job.
+/// - `queued`: The number of jobs sent (or about to be sent) that no worker has picked up yet.
+/// - `min_threads`: The number of workers the pool keeps even when idle.
+/// - `max_threads`: The most workers the pool will run.
+/// - `keep_alive`: How long a worker above `min_threads` may sit idle before it retires.
+/// - `next_id`: The id to give the next worker the pool grows by.
+/// - `workers`: The workers in the pool. The supervisor swaps in replacements for dead workers.
+/// - `events`: The sender end of the supervisor's event channel.
+/// - `panic_handler`: The callback to tell about panicking jobs, if one has been set.
+/// - `metrics`: Counts and timings of the jobs the pool has handled.
+/// - `threads`: How worker threads are spawned, and the hooks they run.
+/// - `logger`: The logger given to the pool, if it doesn't use the global one.
+/// - `watchdog`: The running jobs that have deadlines.
+/// - `keyed`: The lanes that keep jobs with the same key from running at once.
+/// - `idle_lock`: The lock callers waiting for the pool to go idle park on.
+/// - `idle`: Signalled when the last queued or running job finishes.
+pub(crate) struct Shared {
+    pub(crate) queue: JobQueue,
+    pub(crate) capacity: Option<usize>,
+    pub(crate) policy: RejectionPolicy,
+    pub(crate) live_workers: AtomicUsize,
+    pub(crate) busy_workers: AtomicUsize,
+    pub(crate) queued: AtomicUsize,
+    pub(crate) min_threads: AtomicUsize,
+    pub(crate) max_threads: AtomicUsize,
+    pub(crate) keep_alive: Option<Duration>,
+    pub(crate) next_id: AtomicUsize,
+    pub(crate) workers: Mutex<Vec<Worker>>,
+    pub(crate) events: Sender<SupervisorEvent>,
+    pub(crate) panic_handler: Mutex<Option<Arc<PanicHandler>>>,
+    pub(crate) metrics: Metrics,
+    pub(crate) threads: ThreadConfig,
+    pub(crate) logger: Option<Arc<Logger>>,
+    pub(crate) watchdog: Watchdog,
+    pub(crate) keyed: KeyedLanes,
+    pub(crate) idle_lock: Mutex<()>,
+    pub(crate) idle: Condvar,
+}
+
+impl Shared {
+    /// Create the shared state for a new pool, with no workers started yet.
+    /// 
+    /// ## Parameters
+    /// - `sizing`: How many workers the pool runs.
+    /// - `queue`: The job queue.
+    /// - `capacity`: The most jobs the queue holds, if it is bounded.
+    /// - `policy`: What to do with a job submitted while a bounded queue is full.
+    /// - `events`: The sender end of the supervisor's event channel.
+    /// - `threads`: How worker threads are spawned, and the hooks they run.
+    /// - `logger`: The logger to use instead of the global one, if any.
+    pub(crate) fn new(
+        sizing: Sizing,
+        queue: JobQueue,
+        capacity: Option<usize>,
+        policy: RejectionPolicy,
+        events: Sender<SupervisorEvent>,
+        threads: ThreadConfig,
+        logger: Option<Arc<Logger>>,
+    ) -> Shared {
+        Shared {
+            queue,
+            capacity,
+            policy,
+            live_workers: AtomicUsize::new(0),
+            busy_workers: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            min_threads: AtomicUsize::new(sizing.min_threads),
+            max_threads: AtomicUsize::new(sizing.max_threads),
+            keep_alive: sizing.keep_alive,
+            next_id: AtomicUsize::new(0),
+            workers: Mutex::new(Vec::with_capacity(sizing.min_threads)),
+            events,
+            panic_handler: Mutex::new(None),
+            metrics: Metrics::new(),
+            threads,
+            logger,
+            watchdog: Watchdog::new(),
+            keyed: KeyedLanes::new(),
+            idle_lock: Mutex::new(()),
+            idle: Condvar::new(),
+        }
+    }
+
+    /// Hand a job to the workers, applying a `RejectionPolicy` if a bounded queue is full.
+    /// 
+    /// ## Parameters
+    /// - `priority`: How urgently the job should run.
+    /// - `f`: The job.
+    /// - `token`: A token that cancels the job if it is cancelled before the job runs, if any.
+    /// - `deadline`: How long the job may run before the watchdog reports it, if it is watched.
+    /// - `lane`: The lane of a job executed with a key, if it was. The job waits in the lane
+    ///   while another job in it is queued or running, without taking up room in the queue.
+    /// - `policy`: What to do if the queue is full. This is the pool's own policy for jobs
+    ///   submitted through the pool, but may differ for jobs submitted by the pool itself.
+    /// 
+    /// ## Returns
+    /// The same as `ThreadPool::execute`.
+    pub(crate) fn submit<F>(
+        self: &Arc<Self>,
+        priority: Priority,
+        f: F,
+        mut token: Option<CancellationToken>,
+        mut deadline: Option<Deadline>,
+        lane: Option<u64>,
+        policy: RejectionPolicy,
+    ) -> Result<(), ExecuteError<F>>
+    where
+        F: IntoJob
+    {
+        // Refuse the job if the pool is no longer accepting work. The queue checks again as
+        // the job is pushed, in case the pool shuts down in between.
+        if self.queue.is_closed() {
+            self.metrics.reject();
+            return Err(ExecuteError::Shutdown(f));
+        }
+
+        // Refuse the job if there is nobody left to run it, rather than queueing it forever.
+        if self.live_workers.load(Ordering::Acquire) == 0 {
+            self.metrics.reject();
+            return Err(ExecuteError::NoWorkers(f));
+        }
+
+        // Wait behind the job already queued or running in the lane, if there is one.
+        let f = match lane {
+            Some(lane) => {
+                let wait = |f: F| QueuedJob::new(f.into_job(), token.take(), deadline.take(), Some(lane));
+                match self.keyed.claim(lane, f, priority, wait) {
+                    Some(f) => f,
+                    None => return Ok(()),
+                }
+            },
+            None => f,
+        };
+
+        // Start another worker if this job would otherwise have to wait for one.
+        self.grow_if_backed_up();
+
+        let capacity = match self.capacity {
+            Some(capacity) if policy != RejectionPolicy::Block => capacity,
+            // Unbounded queues never fill up, and blocking callers wait on the queue itself for space.
+            _ => {
+                self.queued.fetch_add(1, Ordering::AcqRel);
+                return self.push(priority, f, token, deadline, lane);
+            },
+        };
+
+        loop {
+            // With a slot reserved the push can't block.
+            if self.try_reserve(capacity) {
+                return self.push(priority, f, token, deadline, lane);
+            }
+
+            match policy {
+                RejectionPolicy::Abort => {
+                    self.metrics.reject();
+                    self.release_lane(lane);
+                    return Err(ExecuteError::QueueFull(f));
+                },
+                RejectionPolicy::CallerRuns => {
+                    // Pass the lane on even if the job panics, so the jobs behind it still run.
+                    let outcome = panic::catch_unwind(AssertUnwindSafe(f.into_job()));
+                    self.release_lane(lane);
+                    if let Err(payload) = outcome {
+                        panic::resume_unwind(payload);
+                    }
+                    break;
+                },
+                RejectionPolicy::DiscardOldest => {
+                    self.discard_oldest();
+                    thread::yield_now();
+                },
+                RejectionPolicy::Block => unreachable!("blocking pushes are handled above"),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Push a job that has already been counted in `queued` onto the queue, taking it back
+    /// out of the count if the queue has closed since `submit` checked.
+    /// 
+    /// ## Returns
+    /// The same as `ThreadPool::execute`.
+    fn push<F>(&self, priority: Priority, f: F, token: Option<CancellationToken>, deadline: Option<Deadline>, lane: Option<u64>) -> Result<(), ExecuteError<F>>
+    where
+        F: IntoJob
+    {
+        let pushed = self.queue.push(f, priority, |f| QueuedJob::new(f.into_job(), token, deadline, lane));
+        pushed.map_err(|f| {
+            self.queued.fetch_sub(1, Ordering::AcqRel);
+            self.metrics.reject();
+            self.release_lane(lane);
+            self.notify_if_idle();
+            ExecuteError::Shutdown(f)
+        })
+    }
+
+    /// Reserve a slot in a bounded queue.
+    /// 
+    /// ## Parameters
+    /// - `capacity`: The capacity of the queue.
+    /// 
+    /// ## Returns
+    /// `true` if a slot was reserved, in which case sending a job will not block.
+    pub(crate) fn try_reserve(&self, capacity: usize) -> bool {
+        self.queued
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |queued| (queued < capacity).then_some(queued + 1))
+            .is_ok()
+    }
+
+    /// Drop the lowest-priority job that has been waiting in the queue the longest, if there is one.
+    pub(crate) fn discard_oldest(&self) {
+        // If there's nothing to take, space is about to free up anyway.
+        if let Some(job) = self.queue.pop_oldest() {
+            self.queued.fetch_sub(1, Ordering::AcqRel);
+            self.metrics.reject();
+            self.release_lane(job.lane);
+            self.notify_if_idle();
+        }
+    }
+
+    /// Whether no job is queued or running.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.queued.load(Ordering::Acquire) == 0 && self.busy_workers.load(Ordering::Acquire) == 0
+    }
+
+    /// Wake the callers waiting for the pool to go idle, if it has.
+    pub(crate) fn notify_if_idle(&self) {
+        if self.is_idle() {
+            // Take the lock before notifying so a caller that is about to wait can't miss it.
+            let _idle = lock(&self.idle_lock);
+            self.idle.notify_all();
+        }
+    }
+
+    /// Block until no job is queued or running.
+    /// 
+    /// ## Parameters
+    /// - `deadline`: When to give up waiting, if ever.
+    /// 
+    /// ## Returns
+    /// `true` if the pool went idle, `false` if the deadline passed first.
+    pub(crate) fn wait_idle(&self, deadline: Option<Instant>) -> bool {
+        let mut idle = lock(&self.idle_lock);
+        while !self.is_idle() {
+            idle = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(timeout) if !timeout.is_zero() => self.idle.wait_timeout(idle, timeout).unwrap_or_else(PoisonError::into_inner).0,
+                    _ => return false,
+                },
+                None => self.idle.wait(idle).unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+        true
+    }
+
+    /// Queue a job that has already been let into the pool, without waiting for room or
+    /// applying a `RejectionPolicy`.
+    /// 
+    /// ## Parameters
+    /// - `job`: The job.
+    pub(crate) fn push_ready(&self, job: Job) {
+        self.queued.fetch_add(1, Ordering::AcqRel);
+        self.queue.push_ready(QueuedJob::new(job, None, None, None), Priority::Normal);
+    }
+
+    /// Pass a keyed job's lane on once the job has run or been dropped, queueing the next job
+    /// waiting in it.
+    /// 
+    /// ## Parameters
+    /// - `lane`: The job's lane, or `None` if it wasn't keyed.
+    pub(crate) fn release_lane(&self, lane: Option<u64>) {
+        if let Some((job, priority)) = lane.and_then(|lane| self.keyed.release(lane)) {
+            // The job was let in when it was submitted, so it doesn't wait for room now.
+            self.queued.fetch_add(1, Ordering::AcqRel);
+            self.queue.push_ready(job, priority);
+        }
+    }
+
+    /// Take every job still waiting in the queue or a keyed lane, so that no worker runs it.
+    /// 
+    /// ## Returns
+    /// The jobs taken, leaving out any that have been cancelled.
+    pub(crate) fn drain_queue(&self) -> Vec<Job> {
+        // Empty the lanes first, so a worker passing one on can't queue a job after the queue is drained.
+        let waiting = self.keyed.drain();
+        let mut jobs = self.queue.drain();
+        self.queued.fetch_sub(jobs.len(), Ordering::AcqRel);
+        jobs.extend(waiting);
+
+        let (cancelled, jobs): (Vec<QueuedJob>, Vec<QueuedJob>) = jobs.into_iter().partition(QueuedJob::is_cancelled);
+        self.metrics.cancelled.fetch_add(cancelled.len() as u64, Ordering::Relaxed);
+        jobs.into_iter().map(|queued| queued.job).collect()
+    }
+
+    /// Log an event with the pool's logger, or the global one if it wasn't given its own.
+    /// 
+    /// ## Parameters
+    /// - `level`: How important the event is.
+    /// - `component`: The part of the pool the event came from.
+    /// - `message`: What happened.
+    /// - `fields`: Details of the event as name and value pairs.
+    pub(crate) fn log(&self, level: Level, component: &'static str, message: fmt::Arguments<'_>, fields: &[(&'static str, &dyn fmt::Display)]) {
+        match &self.logger {
+            Some(logger) => logger.log(level, component, message, fields),
+            None => logging::log(level, component, message, fields),
+        }
+    }
+
+    /// Tell the panic handler, if there is one, that a job panicked.
+    /// 
+    /// ## Parameters
+    /// - `id`: The id of the worker that ran the job.
+    /// - `payload`: The panic payload.
+    pub(crate) fn report_panic(&self, id: usize, payload: &(dyn Any + Send)) {
+        // Call the handler outside the lock so that it may replace itself.
+        let handler = lock(&self.panic_handler).clone();
+        if let Some(handler) = handler {
+            // The job is still counted as running, so a handler that panics too mustn't take
+            // the worker down before it stops counting.
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| handler(id, payload)));
+        }
+    }
+
+    /// How long an idle worker waits for a job before checking whether it should retire.
+    pub(crate) fn idle_timeout(&self) -> Duration {
+        self.keep_alive.unwrap_or(IDLE_POLL)
+    }
+
+    /// Take a worker out of the live count if the pool has more workers than it needs.
+    /// 
+    /// ## Parameters
+    /// - `idle`: Whether the worker has been idle for the pool's keep-alive. Idle workers
+    ///   retire down to `min_threads`, others only down to `max_threads`.
+    /// 
+    /// ## Returns
+    /// `true` if the worker should retire. It has already been taken out of the count.
+    pub(crate) fn try_retire(&self, idle: bool) -> bool {
+        let floor = if idle && self.keep_alive.is_some() {
+            self.min_threads.load(Ordering::Acquire)
+        } else {
+            self.max_threads.load(Ordering::Acquire)
+        };
+
+        self.live_workers
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |live| (live > floor).then(|| live - 1))
+            .is_ok()
+    }
+
+    /// Start another worker if every worker is busy or spoken for and the pool is below
+    /// `max_threads`.
+    pub(crate) fn grow_if_backed_up(self: &Arc<Self>) {
+        let live = self.live_workers.load(Ordering::Acquire);
+        let waiting = self.busy_workers.load(Ordering::Acquire) + self.queued.load(Ordering::Acquire);
+        if waiting < live || live >= self.max_threads.load(Ordering::Acquire) {
+            return;
+        }
+
+        if let Err(err) = self.spawn_workers(live + 1) {
+            self.log(Level::Error, "pool", format_args!("failed to grow thread pool: {err}"), &[]);
+        }
+    }
+
+    /// Start workers until at least `count` are live, capped at `max_threads`. Once the
+    /// queue is closed, no more are started.
+    /// 
+    /// ## Parameters
+    /// - `count`: The number of live workers wanted.
+    /// 
+    /// ## Returns
+    /// `Ok(())` once enough workers are live or the queue is closed, or a
+    /// `PoolCreationError` if a worker could not be spawned.
+    pub(crate) fn spawn_workers(self: &Arc<Self>, count: usize) -> Result<(), PoolCreationError> {
+        // Hold the worker list while spawning so that concurrent callers can't overshoot.
+        let mut workers = lock(&self.workers);
+        let count = count.min(self.max_threads.load(Ordering::Acquire));
+
+        // A job executed as the pool shuts down may find it backed up, but the pool may
+        // already have joined its workers, and wouldn't join one started now. The queue is
+        // closed before they are joined, and the list held while they are taken, so checking
+        // here under the list is enough.
+        while !self.queue.is_closed() && self.live_workers.load(Ordering::Acquire) < count {
+            let id = self.next_id.fetch_add(1, Ordering::AcqRel);
+            match Worker::build(id, Arc::clone(self)) {
+                Ok(worker) => workers.push(worker),
+                Err(source) => {
+                    return Err(PoolCreationError::Spawn {
+                        spawned: self.live_workers.load(Ordering::Acquire),
+                        requested: count,
+                        source,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove a worker from the worker list and join its thread.
+    /// 
+    /// ## Parameters
+    /// - `id`: The id of the worker. If the pool has already taken it, this does nothing.
+    pub(crate) fn reap_worker(&self, id: usize) {
+        let worker = {
+            let mut workers = lock(&self.workers);
+            let index = workers.iter().position(|worker| worker.id == id);
+            index.map(|index| workers.swap_remove(index))
+        };
+
+        if let Some(thread) = worker.and_then(|mut worker| worker.thread.take()) {
+            let _ = thread.join();
+        }
+    }
+
+    /// Join every worker, including any replacements started while doing so.
+    /// 
+    /// If this is one of the pool's own workers, because a job dropped the pool's last handle,
+    /// that worker is let go of rather than joined, as it can't join itself. It finds the
+    /// queue closed and exits on its own once the job returns.
+    pub(crate) fn join_workers(self: &Arc<Self>) {
+        let current = worker::current(self);
+
+        // Take the workers one at a time so the supervisor can still get at the list.
+        while let Some(mut worker) = lock(&self.workers).pop() {
+            self.log(Level::Info, "pool", format_args!("shutting down worker"), &[("worker", &worker.id)]);
+            if Some(worker.id) == current {
+                continue;
+            }
+
+            // A worker that died has already been reported to the supervisor, so there's
+            // nothing more to do with its panic here.
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    /// Join every worker that finishes before `deadline`, including any replacements started
+    /// while doing so.
+    /// 
+    /// ## Parameters
+    /// - `deadline`: When to stop waiting. Workers that have already finished are still joined
+    ///   if it has passed.
+    /// 
+    /// ## Returns
+    /// `true` if every worker was joined, `false` if some were still running at the deadline.
+    pub(crate) fn join_workers_until(&self, deadline: Instant) -> bool {
+        loop {
+            let finished = {
+                let mut workers = lock(&self.workers);
+                let (finished, running): (Vec<Worker>, Vec<Worker>) =
+                    mem::take(&mut *workers).into_iter().partition(Worker::is_finished);
+                *workers = running;
+                finished
+            };
+
+            for mut worker in finished {
+                self.log(Level::Info, "pool", format_args!("shutting down worker"), &[("worker", &worker.id)]);
+                if let Some(thread) = worker.thread.take() {
+                    let _ = thread.join();
+                }
+            }
+
+            if lock(&self.workers).is_empty() {
+                return true;
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            thread::sleep(SHUTDOWN_POLL.min(deadline - now));
+        }
+    }
+
+    /// Give up on every worker still in the worker list, leaving its thread to finish on its own.
+    /// 
+    /// ## Returns
+    /// The ids of the workers given up on.
+    pub(crate) fn detach_workers(&self) -> Vec<usize> {
+        lock(&self.workers)
+            .drain(..)
+            .map(|worker| {
+                self.log(Level::Warn, "pool", format_args!("worker is still busy; leaving it running"), &[("worker", &worker.id)]);
+                worker.id
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,23 @@
+use crate::PoolStats;
+
+/// What happened when a `ThreadPool`, or another `Executor`, was shut down with `Executor::shutdown`.
+/// 
+/// ## Fields
+/// - `busy_workers`: The ids of the workers still running a job at the deadline. Their
+///   threads are left to finish the job on their own.
+/// - `discarded`: The number of queued jobs dropped unrun because the deadline passed.
+/// - `stats`: The executor's stats once it had shut down, counting the jobs that finished
+///   while it drained.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    pub busy_workers: Vec<usize>,
+    pub discarded: usize,
+    pub stats: PoolStats,
+}
+
+impl ShutdownReport {
+    /// Whether every queued job ran and every worker stopped before the deadline.
+    pub fn is_complete(&self) -> bool {
+        self.busy_workers.is_empty() && self.discarded == 0
+    }
+}
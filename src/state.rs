@@ -0,0 +1,176 @@
+use std::{any::Any, cell::RefCell, collections::HashMap, marker::PhantomData, panic::{self, AssertUnwindSafe}, sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::Duration};
+
+use crate::{queue::IntoJob, worker, ExecuteError, Job, Priority, ShutdownReport, ThreadPool};
+
+/// The key to give the next `StateInit`.
+static NEXT_KEY: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The states kept on this thread for the `StatefulPool` it works for, by the key of the
+    /// pool's `StateInit`. Each is a `Vec<S>`, holding more than one only if a job ran
+    /// another job while it waited on it. Only a pool's workers keep states, from when they
+    /// start until they stop.
+    static STATES: RefCell<HashMap<usize, Box<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+/// Creates and keeps track of the states of a `StatefulPool`'s workers.
+/// 
+/// ## Fields
+/// - `key`: The key this pool's states are kept under on each thread, unique to the pool.
+/// - `init`: Creates a worker's state, given the worker's id.
+/// - `state`: Ties the type to `S`.
+pub(crate) struct StateInit<S> {
+    key: usize,
+    init: Box<dyn Fn(usize) -> S + Send + Sync>,
+    state: PhantomData<fn() -> S>,
+}
+
+impl<S: 'static> StateInit<S> {
+    /// Wrap a function that creates a worker's state.
+    pub(crate) fn new<I>(init: I) -> StateInit<S>
+    where
+        I: Fn(usize) -> S + Send + Sync + 'static
+    {
+        StateInit {
+            key: NEXT_KEY.fetch_add(1, Ordering::Relaxed),
+            init: Box::new(init),
+            state: PhantomData,
+        }
+    }
+
+    /// Create the state of the worker starting on the current thread, and keep it there for
+    /// its jobs.
+    pub(crate) fn start(&self, id: usize) {
+        let state = (self.init)(id);
+        STATES.with_borrow_mut(|states| states.insert(self.key, Box::new(vec![state])));
+    }
+
+    /// Drop the states kept on the current thread as its worker stops.
+    pub(crate) fn stop(&self) {
+        // Drop them outside the borrow, in case dropping a state uses a stateful pool of its own.
+        let states = STATES.with_borrow_mut(|states| states.remove(&self.key));
+        drop(states);
+    }
+
+    /// Take a state on the current thread for a job to use, creating one if the thread
+    /// doesn't have one spare.
+    fn take(&self) -> S {
+        let spare = STATES.with_borrow_mut(|states| {
+            states.get_mut(&self.key).and_then(|spare| spare.downcast_mut::<Vec<S>>()).and_then(Vec::pop)
+        });
+
+        // Create it outside the borrow, in case `init` uses a stateful pool of its own. A job
+        // run by its caller under `RejectionPolicy::CallerRuns` isn't on one of the pool's
+        // workers, so gets a state of its own, created with the caller's worker id if it has
+        // one or 0 if not, and dropped once the job is done.
+        spare.unwrap_or_else(|| (self.init)(worker::current_id().unwrap_or_default()))
+    }
+
+    /// Keep a state on the current thread for the next job to use, if the thread is one of
+    /// the pool's workers. Otherwise it is dropped.
+    fn put(&self, state: S) {
+        let state = STATES.with_borrow_mut(|states| {
+            match states.get_mut(&self.key).and_then(|spare| spare.downcast_mut::<Vec<S>>()) {
+                Some(spare) => {
+                    spare.push(state);
+                    None
+                },
+                None => Some(state),
+            }
+        });
+        drop(state);
+    }
+
+    /// Run a job with a state from the current thread.
+    /// 
+    /// A job that panics may have left its state half-updated, so the state is dropped and
+    /// the next job gets a fresh one.
+    fn run<F>(&self, f: F)
+    where
+        F: FnOnce(&mut S)
+    {
+        let mut state = self.take();
+        match panic::catch_unwind(AssertUnwindSafe(|| f(&mut state))) {
+            Ok(()) => self.put(state),
+            Err(payload) => {
+                drop(state);
+                panic::resume_unwind(payload);
+            },
+        }
+    }
+}
+
+/// A job executed with `StatefulPool::execute`.
+/// 
+/// ## Fields
+/// - `f`: The job.
+/// - `init`: The pool's states, to take the job's from.
+struct Stateful<F, S> {
+    f: F,
+    init: Arc<StateInit<S>>,
+}
+
+impl<F, S> IntoJob for Stateful<F, S>
+where
+    F: FnOnce(&mut S) + Send + 'static,
+    S: 'static,
+{
+    fn into_job(self) -> Job {
+        let Stateful { f, init } = self;
+        Box::new(move || init.run(f))
+    }
+}
+
+/// A `ThreadPool` whose workers each own a state of type `S`, created by
+/// `ThreadPool::with_state` or `ThreadPoolBuilder::build_with_state`.
+/// 
+/// Each job is given the state of the worker running it, so resources that are expensive to
+/// set up, such as buffers, caches of open files or connections, are reused from one job to
+/// the next rather than set up for every job. The states never leave their worker's thread,
+/// so `S` doesn't need to be `Send`.
+/// 
+/// ## Fields
+/// - `pool`: The pool the jobs run on.
+/// - `init`: Creates the workers' states.
+pub struct StatefulPool<S> {
+    pool: ThreadPool,
+    init: Arc<StateInit<S>>,
+}
+
+impl<S: 'static> StatefulPool<S> {
+    /// Wrap a pool whose workers create their state with `init` as they start.
+    pub(crate) fn new(pool: ThreadPool, init: Arc<StateInit<S>>) -> StatefulPool<S> {
+        StatefulPool { pool, init }
+    }
+
+    /// Execute a job on the pool, giving it the state of the worker that runs it.
+    /// 
+    /// ## Parameters
+    /// - `f`: The job to execute. This must implement `FnOnce(&mut S)`.
+    /// 
+    /// ## Returns
+    /// The same as `ThreadPool::execute`.
+    pub fn execute<F>(&self, f: F) -> Result<(), ExecuteError<F>>
+    where
+        F: FnOnce(&mut S) + Send + 'static
+    {
+        let init = Arc::clone(&self.init);
+        let job = Stateful { f, init };
+        self.pool
+            .shared
+            .submit(Priority::Normal, job, None, None, None, self.pool.shared.policy)
+            .map_err(|err| err.map(|job| job.f))
+    }
+
+    /// The underlying pool, for everything that doesn't need the workers' states, such as
+    /// `stats`, `wait_idle` or executing jobs that don't use the state.
+    pub fn pool(&self) -> &ThreadPool {
+        &self.pool
+    }
+
+    /// Shut the pool down, as `ThreadPool::shutdown` does. Each worker's state is dropped
+    /// on its own thread as the worker exits.
+    pub fn shutdown(self, timeout: Duration) -> ShutdownReport {
+        self.pool.shutdown(timeout)
+    }
+}
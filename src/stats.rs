@@ -0,0 +1,224 @@
+use std::{array, panic::{self, AssertUnwindSafe}, sync::atomic::Ordering, thread, time::{Duration, Instant}};
+
+use crate::sync::AtomicU64;
+
+/// The number of buckets in a `Histogram`. Bucket `i` holds durations under `2^i`
+/// microseconds, and the last one everything longer.
+const BUCKETS: usize = 32;
+
+/// A snapshot of what a `ThreadPool` is doing and has done, from `ThreadPool::stats`.
+/// 
+/// ## Fields
+/// - `queued`: The number of jobs waiting for a worker.
+/// - `running`: The number of workers running a job.
+/// - `idle`: The number of workers waiting for a job.
+/// - `completed`: The number of jobs that have run to completion.
+/// - `panicked`: The number of jobs that have panicked.
+/// - `cancelled`: The number of jobs skipped because their `CancellationToken` was cancelled
+///   before they ran.
+/// - `rejected`: The number of jobs the pool refused, or dropped from a full queue to make
+///   room for another.
+/// - `queue_wait`: How long jobs waited in the queue before a worker picked them up.
+/// - `execution`: How long jobs took to run, whether or not they panicked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub queued: usize,
+    pub running: usize,
+    pub idle: usize,
+    pub completed: u64,
+    pub panicked: u64,
+    pub cancelled: u64,
+    pub rejected: u64,
+    pub queue_wait: Histogram,
+    pub execution: Histogram,
+}
+
+/// A distribution of durations, in buckets whose bounds double from one microsecond up.
+/// 
+/// Percentiles are only as precise as the buckets: they are reported as the upper bound of
+/// the bucket they fall in, so may be up to twice the true value.
+/// 
+/// ## Fields
+/// - `buckets`: The number of durations recorded in each bucket.
+/// - `sum`: The total of every duration recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    buckets: [u64; BUCKETS],
+    sum: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Histogram {
+        Histogram {
+            buckets: [0; BUCKETS],
+            sum: Duration::ZERO,
+        }
+    }
+}
+
+impl Histogram {
+    /// The number of durations recorded.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// The total of every duration recorded.
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    /// The mean of the durations recorded, or `None` if there are none.
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+        (count > 0).then(|| Duration::from_nanos((self.sum.as_nanos() / u128::from(count)) as u64))
+    }
+
+    /// The duration that a fraction `quantile` of the durations recorded were no longer than,
+    /// rounded up to a bucket bound.
+    /// 
+    /// ## Parameters
+    /// - `quantile`: Between `0.0` and `1.0`, so `0.99` for the 99th percentile.
+    /// 
+    /// ## Returns
+    /// The upper bound of the bucket the quantile falls in, `Duration::MAX` if that is the
+    /// last bucket, or `None` if no durations have been recorded.
+    pub fn percentile(&self, quantile: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+
+        let rank = ((quantile.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        self.buckets().find_map(|(bound, bucket)| {
+            seen += bucket;
+            (seen >= rank).then_some(bound)
+        })
+    }
+
+    /// Every bucket with its upper bound, shortest first. The last bucket's bound is
+    /// `Duration::MAX`.
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.buckets.iter().enumerate().map(|(bucket, &count)| (bucket_bound(bucket), count))
+    }
+}
+
+/// The exclusive upper bound of a `Histogram` bucket.
+fn bucket_bound(bucket: usize) -> Duration {
+    if bucket + 1 < BUCKETS {
+        Duration::from_micros(1 << bucket)
+    } else {
+        Duration::MAX
+    }
+}
+
+/// A `Histogram` that workers can record into without taking a lock.
+/// 
+/// ## Fields
+/// - `buckets`: The number of durations recorded in each bucket.
+/// - `sum`: The total of every duration recorded, in nanoseconds.
+pub(crate) struct AtomicHistogram {
+    buckets: [AtomicU64; BUCKETS],
+    sum: AtomicU64,
+}
+
+impl AtomicHistogram {
+    /// Create an empty AtomicHistogram.
+    pub(crate) fn new() -> AtomicHistogram {
+        AtomicHistogram {
+            buckets: array::from_fn(|_| AtomicU64::new(0)),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    /// Record a duration.
+    pub(crate) fn record(&self, duration: Duration) {
+        let micros = duration.as_micros();
+        let bucket = if micros == 0 { 0 } else { (u128::BITS - micros.leading_zeros()) as usize };
+
+        self.buckets[bucket.min(BUCKETS - 1)].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(duration.as_nanos().try_into().unwrap_or(u64::MAX), Ordering::Relaxed);
+    }
+
+    /// Take a snapshot of the durations recorded so far.
+    pub(crate) fn snapshot(&self) -> Histogram {
+        Histogram {
+            buckets: array::from_fn(|bucket| self.buckets[bucket].load(Ordering::Relaxed)),
+            sum: Duration::from_nanos(self.sum.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Counters the workers and the pool update as jobs come and go.
+/// 
+/// ## Fields
+/// - `completed`: The number of jobs that have run to completion.
+/// - `panicked`: The number of jobs that have panicked.
+/// - `cancelled`: The number of jobs skipped because they were cancelled.
+/// - `rejected`: The number of jobs refused or dropped from a full queue.
+/// - `queue_wait`: How long jobs waited in the queue.
+/// - `execution`: How long jobs took to run.
+pub(crate) struct Metrics {
+    pub(crate) completed: AtomicU64,
+    pub(crate) panicked: AtomicU64,
+    pub(crate) cancelled: AtomicU64,
+    pub(crate) rejected: AtomicU64,
+    pub(crate) queue_wait: AtomicHistogram,
+    pub(crate) execution: AtomicHistogram,
+}
+
+impl Metrics {
+    /// Create Metrics with every counter at zero.
+    pub(crate) fn new() -> Metrics {
+        Metrics {
+            completed: AtomicU64::new(0),
+            panicked: AtomicU64::new(0),
+            cancelled: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+            queue_wait: AtomicHistogram::new(),
+            execution: AtomicHistogram::new(),
+        }
+    }
+
+    /// Count a job refused or dropped by the pool.
+    pub(crate) fn reject(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Run a job, timing it and counting whether it completed or panicked. Used by the
+    /// executors other than `ThreadPool`, whose workers keep their counts themselves.
+    /// 
+    /// ## Parameters
+    /// - `queued_at`: When the job was executed.
+    /// - `f`: The job.
+    /// 
+    /// ## Returns
+    /// `Ok(())`, or the job's panic payload if it panicked.
+    pub(crate) fn run(&self, queued_at: Instant, f: impl FnOnce()) -> thread::Result<()> {
+        let started = Instant::now();
+        self.queue_wait.record(started.saturating_duration_since(queued_at));
+        let outcome = panic::catch_unwind(AssertUnwindSafe(f));
+        self.execution.record(started.elapsed());
+
+        match outcome {
+            Ok(()) => self.completed.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.panicked.fetch_add(1, Ordering::Relaxed),
+        };
+        outcome
+    }
+
+    /// Take a snapshot of the counters, along with the current queue and worker counts.
+    pub(crate) fn stats(&self, queued: usize, running: usize, idle: usize) -> PoolStats {
+        PoolStats {
+            queued,
+            running,
+            idle,
+            completed: self.completed.load(Ordering::Relaxed),
+            panicked: self.panicked.load(Ordering::Relaxed),
+            cancelled: self.cancelled.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            queue_wait: self.queue_wait.snapshot(),
+            execution: self.execution.snapshot(),
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use std::{cell::Cell, collections::VecDeque, sync::{atomic::Ordering, mpsc::RecvTimeoutError, Arc, PoisonError}, time::{Duration, Instant}};
+
+use crate::{priority::{Lanes, Priority, QueueDepths}, queue::QueuedJob, shared::lock, sync::{thread_local, AtomicUsize, Condvar, Mutex, RwLock}};
+
+/// The most jobs a worker moves from the injector to its own deque in one go.
+const INJECTOR_BATCH: usize = 16;
+
+/// The bit of `StealingQueue::state` that is set once the queue is closed. The bits below it
+/// count the pending jobs.
+const CLOSED: usize = 1 << (usize::BITS - 1);
+
+/// A worker's own deque of jobs.
+type LocalDeque = Arc<Mutex<VecDeque<QueuedJob>>>;
+
+thread_local! {
+    /// The queue and worker id of the worker running on this thread, if any. Jobs pushed
+    /// from a worker go to its own deque rather than the shared injector.
+    #[allow(clippy::missing_const_for_thread_local, reason = "loom's `thread_local!` has no `const` form")]
+    static CURRENT: Cell<Option<(usize, usize)>> = Cell::new(None);
+}
+
+/// A job queue where every worker has its own deque and steals from the others when it
+/// runs out, instead of every worker contending on one shared receiver.
+/// 
+/// Jobs submitted from outside the pool go to a shared injector, which workers take from
+/// in batches. Jobs submitted by a job go to the deque of the worker running it, whatever
+/// their priority.
+/// 
+/// ## Fields
+/// - `injector`: Jobs submitted from outside the pool, by priority.
+/// - `locals`: The deque of each live worker, with its id, in order of id. A worker's deque
+///   is dropped when the worker exits, so the list only holds the workers running now.
+/// - `state`: The number of jobs in the injector and every deque, with `CLOSED` set once
+///   the pool has stopped sending jobs. Keeping both in one word lets a job be counted and
+///   the queue checked for being open in a single step.
+/// - `sleepers`: The number of workers parked waiting for a job. Only changed while
+///   holding `sleep`.
+/// - `sleep`: The lock idle workers park on.
+/// - `wake`: Signalled when a job is pushed or the queue is closed.
+pub(crate) struct StealingQueue {
+    injector: Mutex<Lanes>,
+    locals: RwLock<Vec<(usize, LocalDeque)>>,
+    state: AtomicUsize,
+    sleepers: AtomicUsize,
+    sleep: Mutex<()>,
+    wake: Condvar,
+}
+
+impl StealingQueue {
+    /// Create an empty StealingQueue.
+    /// 
+    /// ## Parameters
+    /// - `aging`: How long a job waits in the injector before it is treated as one priority
+    ///   level higher.
+    pub(crate) fn new(aging: Duration) -> StealingQueue {
+        StealingQueue {
+            injector: Mutex::new(Lanes::new(aging)),
+            locals: RwLock::new(Vec::new()),
+            state: AtomicUsize::new(0),
+            sleepers: AtomicUsize::new(0),
+            sleep: Mutex::new(()),
+            wake: Condvar::new(),
+        }
+    }
+
+    /// An id for this queue, used to tell whether the current thread is one of its workers.
+    fn key(&self) -> usize {
+        self as *const StealingQueue as usize
+    }
+
+    /// Give a worker its own deque and mark the current thread as that worker's.
+    /// 
+    /// ## Parameters
+    /// - `worker`: The id of the worker running on the current thread.
+    pub(crate) fn register(&self, worker: usize) {
+        let mut locals = self.locals.write().unwrap_or_else(PoisonError::into_inner);
+        if let Err(index) = locals.binary_search_by_key(&worker, |(id, _)| *id) {
+            locals.insert(index, (worker, Arc::new(Mutex::new(VecDeque::new()))));
+        }
+        drop(locals);
+
+        CURRENT.with(|current| current.set(Some((self.key(), worker))));
+    }
+
+    /// Drop an exiting worker's deque, moving any jobs left on it to the injector for the
+    /// other workers to run.
+    /// 
+    /// ## Parameters
+    /// - `worker`: The id of the exiting worker.
+    pub(crate) fn unregister(&self, worker: usize) {
+        if CURRENT.with(Cell::get) == Some((self.key(), worker)) {
+            CURRENT.with(|current| current.set(None));
+        }
+
+        let mut locals = self.locals.write().unwrap_or_else(PoisonError::into_inner);
+        let Ok(index) = locals.binary_search_by_key(&worker, |(id, _)| *id) else { return };
+        let (_, local) = locals.remove(index);
+
+        // Hold the injector while moving the jobs, so they stay counted and findable
+        // throughout.
+        let mut injector = lock(&self.injector);
+        let mut local = lock(&local);
+        let moved = !local.is_empty();
+        for job in local.drain(..) {
+            injector.push(job, Priority::Normal);
+        }
+        drop((local, injector, locals));
+
+        // A worker may have looked in the injector before the jobs arrived and in the
+        // deque after they left, and be parked waiting for them.
+        if moved {
+            let _sleep = lock(&self.sleep);
+            self.wake.notify_all();
+        }
+    }
+
+    /// Get a worker's deque.
+    fn local(&self, worker: usize) -> Option<LocalDeque> {
+        let locals = self.locals.read().unwrap_or_else(PoisonError::into_inner);
+        let index = locals.binary_search_by_key(&worker, |(id, _)| *id).ok()?;
+        Some(Arc::clone(&locals[index].1))
+    }
+
+    /// Add a job to the queue and wake a parked worker to run it, unless the queue has been
+    /// closed.
+    /// 
+    /// ## Parameters
+    /// - `f`: The job to add.
+    /// - `priority`: How urgently the job should run, if it is submitted from outside the pool.
+    /// - `job`: Wraps the job up to be queued. Only called once the job has been accepted.
+    /// 
+    /// ## Returns
+    /// `Ok(())` if the job was queued, or the job back if the queue has been closed.
+    pub(crate) fn push<F>(&self, f: F, priority: Priority, job: impl FnOnce(F) -> QueuedJob) -> Result<(), F> {
+        // Count the job and check the queue is open in one step, so no worker can see the
+        // queue closed and empty while an accepted job is on its way in.
+        if self.state.fetch_add(1, Ordering::SeqCst) & CLOSED != 0 {
+            self.state.fetch_sub(1, Ordering::SeqCst);
+
+            // A worker may have seen the job counted, and be waiting for it to show up.
+            let _sleep = lock(&self.sleep);
+            self.wake.notify_all();
+            return Err(f);
+        }
+
+        self.enqueue(job(f), priority);
+        Ok(())
+    }
+
+    /// Add a job to the queue and wake a parked worker to run it, even if the queue has been
+    /// closed.
+    /// 
+    /// ## Parameters
+    /// - `job`: The job to add.
+    /// - `priority`: How urgently the job should run, if it is submitted from outside the pool.
+    pub(crate) fn push_ready(&self, job: QueuedJob, priority: Priority) {
+        // Count the job before it is visible, so no worker can take it and decrement first.
+        self.state.fetch_add(1, Ordering::SeqCst);
+        self.enqueue(job, priority);
+    }
+
+    /// Put a job that has already been counted as pending where a worker will find it, and
+    /// wake a parked worker to run it.
+    fn enqueue(&self, job: QueuedJob, priority: Priority) {
+        let current = CURRENT.with(Cell::get).filter(|(key, _)| *key == self.key());
+        match current.and_then(|(_, worker)| self.local(worker)) {
+            Some(local) => lock(&local).push_back(job),
+            None => lock(&self.injector).push(job, priority),
+        }
+
+        // Take the lock before notifying so a worker that is about to park can't miss it.
+        if self.sleepers.load(Ordering::SeqCst) > 0 {
+            let _sleep = lock(&self.sleep);
+            self.wake.notify_one();
+        }
+    }
+
+    /// Take a job for a worker, waiting up to `timeout` for one to arrive.
+    /// 
+    /// ## Parameters
+    /// - `worker`: The id of the worker taking the job.
+    /// - `timeout`: How long to wait for a job.
+    /// 
+    /// ## Returns
+    /// The job, `RecvTimeoutError::Timeout` if none arrived in time, or
+    /// `RecvTimeoutError::Disconnected` once the queue is closed and empty.
+    pub(crate) fn pop(&self, worker: usize, timeout: Duration) -> Result<QueuedJob, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(job) = self.find(worker) {
+                return Ok(job);
+            }
+
+            // Look again once counted as a sleeper, so a job pushed since the last look
+            // either turns up now or wakes us. Jobs are uncounted as they are taken, so a job
+            // still pending after this is on its way in, and its pusher will wake us.
+            let sleep = lock(&self.sleep);
+            self.sleepers.fetch_add(1, Ordering::SeqCst);
+            let job = self.find(worker);
+            let state = self.state.load(Ordering::SeqCst);
+            let now = Instant::now();
+
+            if job.is_some() || state == CLOSED || now >= deadline {
+                self.sleepers.fetch_sub(1, Ordering::SeqCst);
+                if job.is_none() && state == CLOSED {
+                    // Another worker may be waiting for a job it saw counted, whose wake-up
+                    // went to whoever took the job.
+                    self.wake.notify_all();
+                }
+                drop(sleep);
+
+                return match job {
+                    Some(job) => Ok(job),
+                    None if state == CLOSED => Err(RecvTimeoutError::Disconnected),
+                    None => Err(RecvTimeoutError::Timeout),
+                };
+            }
+
+            let (sleep, _) = self.wake.wait_timeout(sleep, deadline - now).unwrap_or_else(PoisonError::into_inner);
+            self.sleepers.fetch_sub(1, Ordering::SeqCst);
+            drop(sleep);
+        }
+    }
+
+    /// Look for a job without waiting: first in the worker's own deque, then in the
+    /// injector, then in the other workers' deques.
+    fn find(&self, worker: usize) -> Option<QueuedJob> {
+        let local = self.local(worker);
+
+        // The newest job in our own deque is the one most likely to still be in cache.
+        if let Some(local) = local.as_ref() {
+            let mut local = lock(local);
+            if let Some(job) = local.pop_back() {
+                self.state.fetch_sub(1, Ordering::SeqCst);
+                return Some(job);
+            }
+        }
+
+        // Take a batch from the injector, so we don't come back to it for every job.
+        {
+            let mut injector = lock(&self.injector);
+            if let Some(job) = injector.pop() {
+                // Our own deque is taken from the back, so add the batch in reverse to run
+                // it in priority order.
+                if let Some(local) = local.as_ref() {
+                    let batch = (injector.len() / 2).min(INJECTOR_BATCH);
+                    let batch: Vec<QueuedJob> = (0..batch).filter_map(|_| injector.pop()).collect();
+                    lock(local).extend(batch.into_iter().rev());
+                }
+
+                self.state.fetch_sub(1, Ordering::SeqCst);
+                return Some(job);
+            }
+        }
+
+        // Steal the oldest job from another worker, starting after ourselves so that
+        // thieves spread out over their victims.
+        let locals = self.locals.read().unwrap_or_else(PoisonError::into_inner);
+        let count = locals.len();
+        let start = locals.partition_point(|(id, _)| *id <= worker);
+        for offset in 0..count {
+            let (id, victim) = &locals[(start + offset) % count];
+            if *id == worker {
+                continue;
+            }
+            let mut victim = lock(victim);
+            if let Some(job) = victim.pop_front() {
+                self.state.fetch_sub(1, Ordering::SeqCst);
+                return Some(job);
+            }
+        }
+
+        None
+    }
+
+    /// Take the lowest-priority job that has been queued the longest, if there is one.
+    pub(crate) fn pop_oldest(&self) -> Option<QueuedJob> {
+        let mut injector = lock(&self.injector);
+        if let Some(job) = injector.pop_oldest() {
+            self.state.fetch_sub(1, Ordering::SeqCst);
+            return Some(job);
+        }
+        drop(injector);
+
+        let locals = self.locals.read().unwrap_or_else(PoisonError::into_inner);
+        locals.iter().find_map(|(_, local)| {
+            let mut local = lock(local);
+            let job = local.pop_front()?;
+            self.state.fetch_sub(1, Ordering::SeqCst);
+            Some(job)
+        })
+    }
+
+    /// Take every queued job: those in the injector in priority order, then those on each
+    /// worker's deque from oldest to newest.
+    pub(crate) fn drain(&self) -> Vec<QueuedJob> {
+        let mut injector = lock(&self.injector);
+        let mut jobs = injector.drain();
+        self.state.fetch_sub(jobs.len(), Ordering::SeqCst);
+        drop(injector);
+
+        let locals = self.locals.read().unwrap_or_else(PoisonError::into_inner);
+        for (_, local) in locals.iter() {
+            let mut local = lock(local);
+            self.state.fetch_sub(local.len(), Ordering::SeqCst);
+            jobs.extend(local.drain(..));
+        }
+        jobs
+    }
+
+    /// The number of jobs waiting at each priority. Jobs on the workers' own deques are
+    /// counted as `Priority::Normal`.
+    pub(crate) fn depths(&self) -> QueueDepths {
+        let mut depths = lock(&self.injector).depths();
+        let locals = self.locals.read().unwrap_or_else(PoisonError::into_inner);
+        depths.normal += locals.iter().map(|(_, local)| lock(local).len()).sum::<usize>();
+        depths
+    }
+
+    /// Whether the queue has been closed.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.state.load(Ordering::SeqCst) & CLOSED != 0
+    }
+
+    /// Stop accepting jobs. Workers finish the jobs already queued, then see the queue
+    /// as disconnected.
+    pub(crate) fn close(&self) {
+        self.state.fetch_or(CLOSED, Ordering::SeqCst);
+
+        let _sleep = lock(&self.sleep);
+        self.wake.notify_all();
+    }
+}
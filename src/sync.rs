@@ -0,0 +1,43 @@
+// The synchronisation primitives the pool is built on.
+// 
+// Normally these are the standard library's. Built with `--cfg loom`, they are loom's
+// instead, which lets the model tests run the pool's queues, waits, worker and supervisor
+// threads under every interleaving. `Arc` and `Weak` stay the standard library's either
+// way, since loom has no `Weak`.
+
+#[cfg(not(loom))]
+pub(crate) use std::{sync::{atomic::{fence, AtomicBool, AtomicPtr, AtomicU64, AtomicU8, AtomicUsize}, mpsc, Condvar, Mutex, MutexGuard, RwLock}, thread::{yield_now, Builder, JoinHandle}, thread_local};
+
+#[cfg(loom)]
+pub(crate) use loom::{cell::UnsafeCell, sync::{atomic::{fence, AtomicBool, AtomicPtr, AtomicU64, AtomicU8, AtomicUsize}, mpsc, Condvar, Mutex, MutexGuard, RwLock}, thread::{yield_now, Builder, JoinHandle}, thread_local};
+
+/// Whether a thread has finished, so that joining it won't block.
+#[cfg(not(loom))]
+pub(crate) fn is_finished<T>(thread: &JoinHandle<T>) -> bool {
+    thread.is_finished()
+}
+
+/// Whether a thread has finished, so that joining it won't block. Loom can't tell without
+/// joining, so a thread counts as running until it is joined.
+#[cfg(loom)]
+pub(crate) fn is_finished<T>(_thread: &JoinHandle<T>) -> bool {
+    false
+}
+
+/// A `std::cell::UnsafeCell` with the interface of loom's, which checks that the value is
+/// never accessed from two threads at once.
+#[cfg(not(loom))]
+#[derive(Debug)]
+pub(crate) struct UnsafeCell<T>(std::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    pub(crate) fn new(value: T) -> UnsafeCell<T> {
+        UnsafeCell(std::cell::UnsafeCell::new(value))
+    }
+
+    /// Run `f` with a pointer to the value, which it may write through.
+    pub(crate) fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
@@ -0,0 +1,211 @@
+use std::{cmp::{Ordering as CmpOrdering, Reverse}, collections::BinaryHeap, sync::{Arc, TryLockError}, time::{Duration, Instant}};
+
+use crate::{logging::Level, shared::Shared, sync::Mutex, CancellationToken, ExecuteError, Job, Priority, RejectionPolicy};
+
+/// A job to run on a `ThreadPool` at a later time, returned by `ThreadPool::execute_after`
+/// and `ThreadPool::execute_every`.
+/// 
+/// Dropping the handle doesn't cancel the job.
+/// 
+/// ## Fields
+/// - `cancelled`: Cancelled once the job has been. Each run is queued with it as its
+///   token, so a run still waiting in the queue is skipped too.
+#[derive(Debug, Clone)]
+pub struct ScheduledJob {
+    cancelled: CancellationToken,
+}
+
+impl ScheduledJob {
+    /// Stop the job from running again. A run that has come due but is still waiting for a
+    /// worker is skipped, and counted as cancelled; a run that has already started carries on.
+    pub fn cancel(&self) {
+        self.cancelled.cancel();
+    }
+
+    /// Whether the job has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.is_cancelled()
+    }
+}
+
+/// A periodic job. It is locked while it runs, so a run that comes round while the last one
+/// is still going is skipped rather than overlapping it.
+type Periodic = Arc<Mutex<dyn FnMut() + Send>>;
+
+/// What to do when a timer comes due.
+/// 
+/// ## Variants
+/// - `Once`: Run the job once.
+/// - `Every`: Run the job, then again every `period`.
+pub(crate) enum Task {
+    Once(Job),
+    Every {
+        period: Duration,
+        f: Periodic,
+    },
+}
+
+/// A timer waiting to come due, as sent to the supervisor.
+/// 
+/// ## Fields
+/// - `due`: When the task should next run.
+/// - `task`: What to run.
+/// - `cancelled`: Shared with the `ScheduledJob` handle.
+pub(crate) struct Timer {
+    due: Instant,
+    task: Task,
+    cancelled: CancellationToken,
+}
+
+impl Timer {
+    /// Create a timer, with a handle that can cancel it.
+    /// 
+    /// ## Parameters
+    /// - `delay`: How long from now the task should first run.
+    /// - `task`: What to run.
+    pub(crate) fn new(delay: Duration, task: Task) -> (Timer, ScheduledJob) {
+        let cancelled = CancellationToken::new();
+        let timer = Timer {
+            due: Instant::now() + delay,
+            task,
+            cancelled: cancelled.clone(),
+        };
+        (timer, ScheduledJob { cancelled })
+    }
+
+    /// Wrap a periodic job so that it can be shared between its runs.
+    pub(crate) fn periodic<F>(f: F) -> Periodic
+    where
+        F: FnMut() + Send + 'static
+    {
+        Arc::new(Mutex::new(f))
+    }
+}
+
+/// A timer in the supervisor's heap, ordered by when it is due and then by when it was added.
+/// 
+/// ## Fields
+/// - `seq`: The order the timer was added in, so timers due at the same time run in order.
+/// - `timer`: The timer.
+struct Entry {
+    seq: u64,
+    timer: Timer,
+}
+
+impl Entry {
+    /// The key the heap is ordered by.
+    fn key(&self) -> (Instant, u64) {
+        (self.timer.due, self.seq)
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Entry) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Entry) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Entry) -> CmpOrdering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// The timers waiting to come due, kept by the supervisor.
+/// 
+/// ## Fields
+/// - `heap`: The timers, soonest first.
+/// - `next_seq`: The sequence number to give the next timer added.
+pub(crate) struct Timers {
+    heap: BinaryHeap<Reverse<Entry>>,
+    next_seq: u64,
+}
+
+impl Timers {
+    /// Create an empty set of timers.
+    pub(crate) fn new() -> Timers {
+        Timers {
+            heap: BinaryHeap::new(),
+            next_seq: 0,
+        }
+    }
+
+    /// Add a timer.
+    pub(crate) fn push(&mut self, timer: Timer) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.heap.push(Reverse(Entry { seq, timer }));
+    }
+
+    /// When the soonest timer comes due, if there are any.
+    pub(crate) fn next_due(&self) -> Option<Instant> {
+        self.heap.peek().map(|Reverse(entry)| entry.timer.due)
+    }
+
+    /// Hand every timer that has come due to the workers, and set periodic ones going again.
+    /// 
+    /// The supervisor mustn't block here, so a job that finds a bounded queue full is skipped
+    /// instead of waiting or being run on the supervisor's thread.
+    /// 
+    /// ## Parameters
+    /// - `shared`: The state shared with the pool, to submit the jobs through.
+    pub(crate) fn fire(&mut self, shared: &Arc<Shared>) {
+        let policy = match shared.policy {
+            RejectionPolicy::Block | RejectionPolicy::CallerRuns => RejectionPolicy::Abort,
+            policy => policy,
+        };
+
+        let now = Instant::now();
+        while self.next_due().is_some_and(|due| due <= now) {
+            let Some(Reverse(Entry { timer, .. })) = self.heap.pop() else { break };
+            if timer.cancelled.is_cancelled() {
+                continue;
+            }
+
+            match timer.task {
+                Task::Once(job) => {
+                    if let Err(err) = shared.submit(Priority::Normal, job, Some(timer.cancelled), None, None, policy) {
+                        shared.log(Level::Warn, "supervisor", format_args!("dropped delayed job: {err}"), &[]);
+                    }
+                },
+                Task::Every { period, f } => {
+                    let run = Arc::clone(&f);
+                    let job = move || {
+                        let mut f = match run.try_lock() {
+                            Ok(f) => f,
+                            // Carry on after a run that panicked, as the pool does with any other job.
+                            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+                            Err(TryLockError::WouldBlock) => return,
+                        };
+                        (*f)();
+                    };
+
+                    match shared.submit(Priority::Normal, job, Some(timer.cancelled.clone()), None, None, policy) {
+                        Err(ExecuteError::Shutdown(_)) => continue,
+                        Err(err) => shared.log(Level::Warn, "supervisor", format_args!("skipped periodic job: {err}"), &[]),
+                        Ok(()) => {},
+                    }
+
+                    // If the job fell a whole period behind, carry on from now rather than catching up.
+                    let mut due = timer.due + period;
+                    if due <= now {
+                        due = now + period;
+                    }
+                    self.push(Timer {
+                        due,
+                        task: Task::Every { period, f },
+                        cancelled: timer.cancelled,
+                    });
+                },
+            }
+        }
+    }
+}
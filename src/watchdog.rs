@@ -0,0 +1,206 @@
+use std::{collections::{HashMap, HashSet}, sync::{atomic::Ordering, Arc}, time::{Duration, Instant}};
+
+use crate::{logging::Level, shared::{lock, Shared}, sync::{AtomicUsize, Mutex}, worker::Worker};
+
+/// A job's deadline, as given to `ThreadPool::execute_with_deadline`.
+/// 
+/// ## Fields
+/// - `label`: A name for the job to report it by.
+/// - `budget`: How long the job may run before it is reported.
+pub(crate) struct Deadline {
+    pub(crate) label: String,
+    pub(crate) budget: Duration,
+}
+
+/// A job with a deadline that is running on a worker.
+/// 
+/// ## Fields
+/// - `deadline`: The job's deadline.
+/// - `started`: When the job started running.
+/// - `reported`: Whether the job has been reported as overdue.
+struct WatchedJob {
+    deadline: Deadline,
+    started: Instant,
+    reported: bool,
+}
+
+/// A job that has been running for longer than its deadline, from `ThreadPool::overdue_jobs`.
+/// 
+/// ## Fields
+/// - `worker`: The id of the worker running the job.
+/// - `label`: The label the job was given.
+/// - `elapsed`: How long the job has been running.
+/// - `deadline`: How long the job was meant to take.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverdueJob {
+    pub worker: usize,
+    pub label: String,
+    pub elapsed: Duration,
+    pub deadline: Duration,
+}
+
+/// Keeps track of the running jobs that have deadlines, so the supervisor can report the ones
+/// that overrun.
+/// 
+/// Only jobs executed with a deadline are tracked, so other jobs don't pay for it.
+/// 
+/// ## Fields
+/// - `state`: The jobs being watched and the workers that have been replaced.
+/// - `replaced`: The number of replaced workers that have yet to retire, so that workers can
+///   skip the lock after every job while there are none.
+pub(crate) struct Watchdog {
+    state: Mutex<WatchState>,
+    replaced: AtomicUsize,
+}
+
+/// The state of a `Watchdog`, guarded by its lock.
+/// 
+/// ## Fields
+/// - `jobs`: The running jobs with deadlines, by the id of the worker running them. A worker
+///   waiting on other jobs may run more jobs in the meantime, so each holds a stack of them,
+///   innermost last.
+/// - `replaced`: The workers a replacement has been started for, which retire once they are
+///   back from their current job.
+struct WatchState {
+    jobs: HashMap<usize, Vec<WatchedJob>>,
+    replaced: HashSet<usize>,
+}
+
+impl Watchdog {
+    /// Create a Watchdog with no jobs to watch.
+    pub(crate) fn new() -> Watchdog {
+        Watchdog {
+            state: Mutex::new(WatchState {
+                jobs: HashMap::new(),
+                replaced: HashSet::new(),
+            }),
+            replaced: AtomicUsize::new(0),
+        }
+    }
+
+    /// Start watching a job that a worker is about to run.
+    /// 
+    /// ## Parameters
+    /// - `worker`: The id of the worker.
+    /// - `deadline`: The job's deadline.
+    pub(crate) fn start(&self, worker: usize, deadline: Deadline) {
+        lock(&self.state).jobs.entry(worker).or_default().push(WatchedJob {
+            deadline,
+            started: Instant::now(),
+            reported: false,
+        });
+    }
+
+    /// Stop watching the innermost job a worker is running, once it has finished.
+    /// 
+    /// ## Parameters
+    /// - `worker`: The id of the worker.
+    pub(crate) fn finish(&self, worker: usize) {
+        let mut state = lock(&self.state);
+        if let Some(jobs) = state.jobs.get_mut(&worker) {
+            jobs.pop();
+            if jobs.is_empty() {
+                state.jobs.remove(&worker);
+            }
+        }
+    }
+
+    /// Check whether a replacement has been started for a worker since this was last asked.
+    /// 
+    /// ## Parameters
+    /// - `worker`: The id of the worker.
+    /// 
+    /// ## Returns
+    /// `true` if one has, in which case the worker has already been taken out of the live
+    /// count and should retire.
+    pub(crate) fn take_replaced(&self, worker: usize) -> bool {
+        if self.replaced.load(Ordering::Acquire) == 0 || !lock(&self.state).replaced.remove(&worker) {
+            return false;
+        }
+        self.replaced.fetch_sub(1, Ordering::AcqRel);
+        true
+    }
+
+    /// When the next job that hasn't been reported yet will overrun its deadline.
+    pub(crate) fn next_check(&self) -> Option<Instant> {
+        lock(&self.state)
+            .jobs
+            .values()
+            .flatten()
+            .filter(|job| !job.reported)
+            .map(|job| job.started + job.deadline.budget)
+            .min()
+    }
+
+    /// Every job that is running past its deadline.
+    pub(crate) fn overdue(&self) -> Vec<OverdueJob> {
+        let now = Instant::now();
+        lock(&self.state)
+            .jobs
+            .iter()
+            .flat_map(|(&worker, jobs)| jobs.iter().map(move |job| (worker, job)))
+            .filter_map(|(worker, job)| {
+                let elapsed = now.saturating_duration_since(job.started);
+                (elapsed > job.deadline.budget).then(|| OverdueJob {
+                    worker,
+                    label: job.deadline.label.clone(),
+                    elapsed,
+                    deadline: job.deadline.budget,
+                })
+            })
+            .collect()
+    }
+
+    /// Report every job that has overrun its deadline since the last check, and start a
+    /// replacement for each of their workers if the pool is set up to.
+    /// 
+    /// ## Parameters
+    /// - `shared`: The state shared with the pool, to log through and spawn workers into.
+    pub(crate) fn check(&self, shared: &Arc<Shared>) {
+        let now = Instant::now();
+        let mut replace = Vec::new();
+
+        let mut state = lock(&self.state);
+        let WatchState { jobs, replaced } = &mut *state;
+        for (&worker, job) in jobs.iter_mut().flat_map(|(worker, jobs)| jobs.iter_mut().map(move |job| (worker, job))) {
+            let elapsed = now.saturating_duration_since(job.started);
+            if job.reported || elapsed < job.deadline.budget {
+                continue;
+            }
+
+            job.reported = true;
+            shared.log(
+                Level::Warn,
+                "watchdog",
+                format_args!("job has overrun its deadline"),
+                &[("worker", &worker), ("job", &job.deadline.label), ("elapsed", &format_args!("{elapsed:?}"))],
+            );
+
+            // Write the stuck worker off now, rather than when its job finishes, so that a
+            // replacement fits within the pool's size. A worker is only replaced once.
+            if shared.threads.replace_stuck_workers && replaced.insert(worker) {
+                self.replaced.fetch_add(1, Ordering::AcqRel);
+                shared.live_workers.fetch_sub(1, Ordering::AcqRel);
+                replace.push(worker);
+            }
+        }
+        drop(state);
+
+        for stuck in replace {
+            let id = shared.next_id.fetch_add(1, Ordering::AcqRel);
+            shared.log(Level::Info, "watchdog", format_args!("starting a replacement for a stuck worker"), &[("worker", &id)]);
+            match Worker::build(id, Arc::clone(shared)) {
+                Ok(worker) => lock(&shared.workers).push(worker),
+                Err(err) => {
+                    shared.log(Level::Error, "watchdog", format_args!("failed to start a replacement worker: {err}"), &[("worker", &id)]);
+
+                    // Keep the stuck worker on instead, counted as live again, unless it has
+                    // already come back and retired.
+                    if self.take_replaced(stuck) {
+                        shared.live_workers.fetch_add(1, Ordering::AcqRel);
+                    }
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use std::{cell::RefCell, io, panic::{self, AssertUnwindSafe}, sync::{atomic::Ordering, mpsc::RecvTimeoutError, Arc, Weak}, thread, time::{Duration, Instant}};
+
+use crate::{builder::Hooks, logging::Level, queue::QueuedJob, shared::{lock, Shared}, sync::{self, mpsc::Receiver, thread_local, Builder, JoinHandle}, timer::{Timer, Timers}};
+
+/// How long a worker waiting on other jobs sleeps when there is nothing queued for it to run
+/// before looking again.
+pub(crate) const HELP_POLL: Duration = Duration::from_millis(1);
+
+thread_local! {
+    /// The pool and id of the worker running on this thread, if any, so a job waiting on
+    /// other jobs can run queued jobs instead of holding up one of the pool's workers.
+    #[allow(clippy::missing_const_for_thread_local, reason = "loom's `thread_local!` has no `const` form")]
+    static CURRENT: RefCell<Option<(Weak<Shared>, usize)>> = RefCell::new(None);
+}
+
+/// The id of the worker running on the current thread, if it is one of the pool's.
+pub(crate) fn current(shared: &Arc<Shared>) -> Option<usize> {
+    CURRENT.with(|current| {
+        current.borrow().as_ref().filter(|(pool, _)| pool.as_ptr() == Arc::as_ptr(shared)).map(|(_, id)| *id)
+    })
+}
+
+/// The id of the worker running on the current thread, whichever pool it belongs to.
+pub(crate) fn current_id() -> Option<usize> {
+    CURRENT.with(|current| current.borrow().as_ref().map(|(_, id)| *id))
+}
+
+/// Whether the current thread is a worker of any pool.
+pub(crate) fn is_worker() -> bool {
+    current_id().is_some()
+}
+
+/// Run a job from the queue on the current thread, if it is a pool's worker and that pool
+/// has a job waiting.
+/// 
+/// A job that waits on other jobs, as `ThreadPool::scope` does, calls this while it waits.
+/// Otherwise, once every worker was waiting, the jobs they wait on would never be run.
+/// 
+/// ## Returns
+/// `true` if a job was run.
+pub(crate) fn help() -> bool {
+    let Some((shared, id)) = CURRENT.with(|current| current.borrow().clone()) else { return false };
+    let Some(shared) = shared.upgrade() else { return false };
+    match shared.queue.recv_timeout(id, Duration::ZERO) {
+        Ok(job) => {
+            run(&shared, id, job);
+            true
+        },
+        Err(_) => false,
+    }
+}
+
+/// Run a job taken from the queue, keeping the pool's counts and hooks up to date. A
+/// panicking job, or job hook, is reported rather than taking the worker down with it.
+/// 
+/// A `before_job` hook that panics counts as the job panicking, and the job is dropped
+/// without being run. An `after_job` hook that panics after a job that didn't counts as
+/// the job panicking too, so each job is counted once either way.
+/// 
+/// ## Parameters
+/// - `shared`: The state shared with the pool.
+/// - `id`: The id of the worker running the job.
+/// - `job`: The job.
+fn run(shared: &Arc<Shared>, id: usize, job: QueuedJob) {
+    // Skip the job if it was cancelled while it waited.
+    if job.is_cancelled() {
+        shared.queued.fetch_sub(1, Ordering::AcqRel);
+        shared.release_lane(job.lane);
+        shared.metrics.cancelled.fetch_add(1, Ordering::Relaxed);
+        shared.log(Level::Debug, "worker", format_args!("skipped a cancelled job"), &[("worker", &id)]);
+        shared.notify_if_idle();
+        return;
+    }
+
+    let QueuedJob { job, deadline, lane, queued_at, .. } = job;
+    let hooks = &shared.threads.hooks;
+    shared.queued.fetch_sub(1, Ordering::AcqRel);
+    shared.busy_workers.fetch_add(1, Ordering::AcqRel);
+    shared.log(Level::Debug, "worker", format_args!("got a job; executing"), &[("worker", &id)]);
+
+    // Have the watchdog keep an eye on a job with a deadline. The supervisor may be asleep
+    // with nothing else to wait for, so nudge it.
+    let watched = deadline.is_some();
+    if let Some(deadline) = deadline {
+        shared.watchdog.start(id, deadline);
+        let _ = shared.events.send(SupervisorEvent::Watch);
+    }
+
+    let started = Instant::now();
+    shared.metrics.queue_wait.record(started.saturating_duration_since(queued_at));
+    let mut outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        Hooks::run(&hooks.before_job, id);
+        job();
+    }));
+    shared.metrics.execution.record(started.elapsed());
+
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| Hooks::run(&hooks.after_job, id))) {
+        match outcome {
+            Ok(()) => outcome = Err(payload),
+            Err(_) => shared.report_panic(id, &*payload),
+        }
+    }
+
+    if watched {
+        shared.watchdog.finish(id);
+    }
+
+    match outcome {
+        Ok(()) => shared.metrics.completed.fetch_add(1, Ordering::Relaxed),
+        Err(payload) => {
+            shared.report_panic(id, &*payload);
+            shared.metrics.panicked.fetch_add(1, Ordering::Relaxed)
+        },
+    };
+
+    // Only stop counting the job once it has been counted and reported, so a caller that
+    // sees the pool go idle sees it too. Queue the next job in the lane first, so the pool
+    // never looks idle in between.
+    shared.release_lane(lane);
+    shared.busy_workers.fetch_sub(1, Ordering::AcqRel);
+    shared.notify_if_idle();
+}
+
+/// A worker that executes jobs.
+/// 
+/// ## Fields
+/// - `id`: The id of the worker.
+/// - `thread`: The thread that the worker is running on.
+pub(crate) struct Worker {
+    pub(crate) id: usize,
+    pub(crate) thread: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    /// Create a new Worker.
+    /// 
+    /// ## Parameters
+    /// - `id`: The id of the worker.
+    /// - `shared`: The state shared with the pool, including the job queue.
+    ///   The pool's live worker count is incremented here and decremented when the thread
+    ///   exits, however it exits.
+    /// 
+    /// ## Returns
+    /// A new `Worker` with the given id, or the `io::Error` returned by the operating
+    /// system if the thread could not be spawned.
+    /// 
+    pub(crate) fn build(id: usize, shared: Arc<Shared>) -> io::Result<Worker> {
+        // Count the worker as live. The guard moves into the thread and undoes this when the
+        // thread exits, or straight away if the thread is never spawned.
+        let live = LiveGuard::new(id, shared);
+
+        // Create a new thread that will run the worker's main loop.
+        let thread = live.shared.threads.builder(id).spawn(move || {
+            let mut live = live;
+            let shared = Arc::clone(&live.shared);
+            let hooks = &shared.threads.hooks;
+            shared.queue.register(id);
+            CURRENT.with(|current| *current.borrow_mut() = Some((Arc::downgrade(&shared), id)));
+            Hooks::run(&hooks.on_thread_start, id);
+
+            loop {
+                // Receive a job from the queue. If there are no jobs, this call will block
+                // until one arrives or the worker has been idle for a while.
+                let message = shared.queue.recv_timeout(id, shared.idle_timeout());
+
+                // If the message is an error, the queue has been closed and the worker should shut down.
+                match message {
+                    // If the message is Ok, execute the job.
+                    Ok(job) => {
+                        run(&shared, id, job);
+
+                        // Retire if the watchdog started a replacement while a job was stuck; it
+                        // has already taken this worker out of the live count.
+                        if shared.watchdog.take_replaced(id) {
+                            live.retired = true;
+                        }
+
+                        // Retire straight away if the pool has been resized below its current size.
+                        if !live.retired && shared.try_retire(false) {
+                            live.retired = true;
+                        }
+                    },
+                    // If the worker has been idle for the pool's keep-alive, it may no longer be needed.
+                    Err(RecvTimeoutError::Timeout) => {
+                        if shared.try_retire(true) {
+                            live.retired = true;
+                        }
+                    },
+                    // If the message is an error, the queue has been closed and the worker should shut down.
+                    Err(RecvTimeoutError::Disconnected) => {
+                        shared.log(Level::Info, "worker", format_args!("shutting down"), &[("worker", &id)]);
+                        break;
+                    },
+                }
+
+                if live.retired {
+                    shared.log(Level::Info, "worker", format_args!("retiring"), &[("worker", &id)]);
+                    break;
+                }
+            }
+
+            Hooks::run(&hooks.on_thread_stop, id);
+        })?;
+
+        Ok(Worker { 
+            id, 
+            thread: Some(thread), 
+        })
+    }
+
+    /// Whether the worker's thread has finished, so that joining it won't block.
+    pub(crate) fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(sync::is_finished)
+    }
+}
+
+/// Decrements the pool's live worker count when dropped, and tells the supervisor that
+/// the worker's thread needs reaping if it retired or replacing if it panicked.
+/// 
+/// ## Fields
+/// - `id`: The id of the worker.
+/// - `shared`: The shared state holding the count.
+/// - `retired`: Whether the worker retired, in which case it has already been taken out
+///   of the count.
+struct LiveGuard {
+    id: usize,
+    shared: Arc<Shared>,
+    retired: bool,
+}
+
+impl LiveGuard {
+    /// Increment the live worker count and return a guard that will decrement it again.
+    fn new(id: usize, shared: Arc<Shared>) -> LiveGuard {
+        shared.live_workers.fetch_add(1, Ordering::AcqRel);
+        LiveGuard { id, shared, retired: false }
+    }
+}
+
+impl Drop for LiveGuard {
+    fn drop(&mut self) {
+        // Hand any jobs left on the worker's own deque to the others. This comes before the
+        // supervisor hears of the exit, so a replacement with the same id starts afresh.
+        self.shared.queue.unregister(self.id);
+
+        if self.retired {
+            let _ = self.shared.events.send(SupervisorEvent::WorkerRetired(self.id));
+            return;
+        }
+
+        self.shared.live_workers.fetch_sub(1, Ordering::AcqRel);
+
+        // Workers only exit otherwise once the channel is closed, so a panic here means the
+        // pool has lost a worker it still needs.
+        if thread::panicking() {
+            let _ = self.shared.events.send(SupervisorEvent::WorkerDied(self.id));
+        }
+    }
+}
+
+/// A message for the pool's supervisor thread.
+/// 
+/// ## Variants
+/// - `WorkerDied`: The worker with this id exited unexpectedly and should be replaced.
+/// - `WorkerRetired`: The worker with this id retired and its thread should be joined.
+/// - `Schedule`: A job to hand to the workers once its timer comes due.
+/// - `Watch`: A worker has started a job with a deadline for the watchdog to check.
+/// - `Shutdown`: The pool is shutting down and the supervisor should exit.
+pub(crate) enum SupervisorEvent {
+    WorkerDied(usize),
+    WorkerRetired(usize),
+    Schedule(Timer),
+    Watch,
+    Shutdown,
+}
+
+/// Spawn the supervisor thread, which replaces workers that die unexpectedly, reaps
+/// workers that retire, hands delayed and periodic jobs to the workers when they
+/// come due, and acts as the watchdog for jobs with deadlines.
+/// 
+/// ## Parameters
+/// - `shared`: The state shared with the pool and its workers.
+/// - `events`: The receiving end of the supervisor's event channel.
+/// 
+/// ## Returns
+/// The supervisor's thread handle, or the `io::Error` returned by the operating system
+/// if the thread could not be spawned.
+pub(crate) fn spawn_supervisor(shared: Arc<Shared>, events: Receiver<SupervisorEvent>) -> io::Result<JoinHandle<()>> {
+    Builder::new().name(format!("{}-supervisor", shared.threads.name)).spawn(move || {
+        let mut timers = Timers::new();
+
+        loop {
+            // Wait for an event, or until the next timer comes due or watched job overruns.
+            let wake = match (timers.next_due(), shared.watchdog.next_check()) {
+                (Some(due), Some(check)) => Some(due.min(check)),
+                (due, check) => due.or(check),
+            };
+            let event = match wake {
+                Some(wake) => events.recv_timeout(wake.saturating_duration_since(Instant::now())),
+                None => events.recv().map_err(RecvTimeoutError::from),
+            };
+            timers.fire(&shared);
+            shared.watchdog.check(&shared);
+
+            let id = match event {
+                Ok(SupervisorEvent::WorkerDied(id)) => id,
+                Ok(SupervisorEvent::WorkerRetired(id)) => {
+                    shared.reap_worker(id);
+                    continue;
+                },
+                Ok(SupervisorEvent::Schedule(timer)) => {
+                    timers.push(timer);
+                    timers.fire(&shared);
+                    continue;
+                },
+                Ok(SupervisorEvent::Watch) | Err(RecvTimeoutError::Timeout) => continue,
+                Ok(SupervisorEvent::Shutdown) | Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            // Reap the dead worker's thread. The pool may already have done so if it is shutting down.
+            shared.reap_worker(id);
+
+            // Start a replacement with the same id so the pool keeps its size.
+            shared.log(Level::Warn, "supervisor", format_args!("worker died; starting a replacement"), &[("worker", &id)]);
+            match Worker::build(id, Arc::clone(&shared)) {
+                Ok(worker) => lock(&shared.workers).push(worker),
+                Err(err) => shared.log(Level::Error, "supervisor", format_args!("failed to replace worker: {err}"), &[("worker", &id)]),
+            }
+        }
+    })
+}
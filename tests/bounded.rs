@@ -0,0 +1,100 @@
+use std::{sync::{atomic::{AtomicBool, Ordering}, mpsc, Arc}, thread, time::Duration};
+use server_rs::{ExecuteError, RejectionPolicy, ThreadPool};
+
+mod common;
+use common::{hold_worker, wait_for_queued, Held, TIMEOUT};
+
+/// Build a one-thread pool with room for one queued job, and fill both the worker and the queue.
+/// 
+/// ## Returns
+/// The pool, the job holding its worker, and a flag set once the queued job has run.
+fn full_pool(policy: RejectionPolicy) -> (ThreadPool, Held, Arc<AtomicBool>) {
+    let pool = ThreadPool::build_bounded(1, 1, policy).unwrap();
+    let held = hold_worker(&pool);
+
+    let queued_ran = Arc::new(AtomicBool::new(false));
+    let ran = Arc::clone(&queued_ran);
+    pool.execute(move || ran.store(true, Ordering::SeqCst)).unwrap();
+    wait_for_queued(&pool, 1);
+
+    (pool, held, queued_ran)
+}
+
+#[test]
+fn abort_hands_the_rejected_job_back() {
+    let (pool, held, queued_ran) = full_pool(RejectionPolicy::Abort);
+    let (done_tx, done_rx) = mpsc::channel();
+
+    let rejected = match pool.execute(move || done_tx.send(()).unwrap()) {
+        Err(err @ ExecuteError::QueueFull(_)) => err.into_inner(),
+        other => panic!("expected the queue to be full, got {other:?}"),
+    };
+    assert_eq!(pool.stats().rejected, 1);
+
+    // The job that was handed back is untouched, and can still be run.
+    rejected();
+    done_rx.recv_timeout(TIMEOUT).unwrap();
+
+    held.release();
+    pool.wait_idle();
+    assert!(queued_ran.load(Ordering::SeqCst));
+}
+
+#[test]
+fn caller_runs_runs_the_rejected_job_on_the_calling_thread() {
+    let (pool, held, queued_ran) = full_pool(RejectionPolicy::CallerRuns);
+    let (ran_on_tx, ran_on_rx) = mpsc::channel();
+
+    pool.execute(move || ran_on_tx.send(thread::current().id()).unwrap()).unwrap();
+
+    // The job has already run by the time `execute` returns.
+    assert_eq!(ran_on_rx.try_recv().unwrap(), thread::current().id());
+    assert!(!queued_ran.load(Ordering::SeqCst));
+
+    held.release();
+    pool.wait_idle();
+    assert!(queued_ran.load(Ordering::SeqCst));
+}
+
+#[test]
+fn discard_oldest_drops_the_queued_job_for_the_new_one() {
+    let (pool, held, queued_ran) = full_pool(RejectionPolicy::DiscardOldest);
+    let newest_ran = Arc::new(AtomicBool::new(false));
+
+    let ran = Arc::clone(&newest_ran);
+    pool.execute(move || ran.store(true, Ordering::SeqCst)).unwrap();
+    assert_eq!(pool.stats().rejected, 1);
+
+    held.release();
+    pool.wait_idle();
+    assert!(!queued_ran.load(Ordering::SeqCst));
+    assert!(newest_ran.load(Ordering::SeqCst));
+}
+
+#[test]
+fn block_waits_for_room_before_queueing_the_job() {
+    let (pool, held, queued_ran) = full_pool(RejectionPolicy::Block);
+    let pool = Arc::new(pool);
+    let newest_ran = Arc::new(AtomicBool::new(false));
+    let (submitted_tx, submitted_rx) = mpsc::channel();
+
+    let submitter = {
+        let pool = Arc::clone(&pool);
+        let ran = Arc::clone(&newest_ran);
+        thread::spawn(move || {
+            pool.execute(move || ran.store(true, Ordering::SeqCst)).unwrap();
+            submitted_tx.send(()).unwrap();
+        })
+    };
+
+    // Nothing frees up space while the worker is held, so the submitter stays blocked.
+    assert!(submitted_rx.recv_timeout(Duration::from_millis(50)).is_err());
+    assert_eq!(pool.stats().rejected, 0);
+
+    held.release();
+    submitted_rx.recv_timeout(TIMEOUT).unwrap();
+    submitter.join().unwrap();
+    pool.wait_idle();
+    assert!(queued_ran.load(Ordering::SeqCst));
+    assert!(newest_ran.load(Ordering::SeqCst));
+}
@@ -0,0 +1,40 @@
+use std::{error::Error, time::Duration};
+use server_rs::{PoolCreationError, RejectionPolicy, Scheduler, ThreadPool};
+
+#[test]
+fn pool_needs_at_least_one_thread() {
+    assert!(matches!(ThreadPool::build(0), Err(PoolCreationError::ZeroSize)));
+    assert!(matches!(ThreadPool::build_elastic(0, 4, Duration::from_secs(1)), Err(PoolCreationError::ZeroSize)));
+    assert!(matches!(ThreadPool::builder().num_threads(0).build(), Err(PoolCreationError::ZeroSize)));
+}
+
+#[test]
+fn max_threads_must_be_at_least_num_threads() {
+    let Err(err) = ThreadPool::build_elastic(4, 2, Duration::from_secs(1)) else {
+        panic!("built a pool with fewer max_threads than min_threads");
+    };
+    assert!(matches!(err, PoolCreationError::MaxBelowMin { min_threads: 4, max_threads: 2 }));
+    assert_eq!(err.to_string(), "thread pool max_threads (2) must be at least min_threads (4)");
+    assert!(err.source().is_none());
+}
+
+#[test]
+fn bounded_queue_needs_room_for_a_job() {
+    assert!(matches!(ThreadPool::build_bounded(2, 0, RejectionPolicy::Abort), Err(PoolCreationError::ZeroCapacity)));
+}
+
+#[test]
+fn work_stealing_pool_cannot_be_bounded() {
+    let built = ThreadPool::builder().scheduler(Scheduler::WorkStealing).bounded(8, RejectionPolicy::Block).build();
+    assert!(matches!(built, Err(PoolCreationError::BoundedWorkStealing)));
+}
+
+#[test]
+fn failing_to_spawn_a_worker_reports_how_far_it_got() {
+    // No system can give a thread a stack this large.
+    let Err(err) = ThreadPool::builder().num_threads(2).stack_size(1 << 60).build() else {
+        panic!("built a pool with impossibly large stacks");
+    };
+    assert!(matches!(err, PoolCreationError::Spawn { spawned: 0, requested: 2, .. }));
+    assert!(err.source().is_some());
+}
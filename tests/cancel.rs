@@ -0,0 +1,59 @@
+mod common;
+
+use std::{sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc}, time::Duration};
+use server_rs::{CancellationToken, ThreadPool};
+
+use common::hold_worker;
+
+#[test]
+fn cancelled_queued_job_is_counted_and_never_run() {
+    let pool = ThreadPool::build(1).unwrap();
+
+    // Hold the only worker so the cancellable jobs stay queued.
+    let held = hold_worker(&pool);
+
+    let token = CancellationToken::new();
+    let other = CancellationToken::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+    for token in [&token, &token, &other] {
+        let runs = Arc::clone(&runs);
+        pool.execute_cancellable(token, move |_| {
+            runs.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+    }
+
+    token.cancel();
+    held.release();
+    pool.wait_idle();
+
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    let stats = pool.stats();
+    assert_eq!(stats.cancelled, 2);
+    assert_eq!(stats.completed, 2);
+}
+
+#[test]
+fn running_job_sees_its_token_cancelled() {
+    let pool = ThreadPool::build(1).unwrap();
+    let (started_tx, started_rx) = mpsc::channel();
+    let (stopped_tx, stopped_rx) = mpsc::channel();
+
+    let token = CancellationToken::new();
+    pool.execute_cancellable(&token, move |token| {
+        started_tx.send(()).unwrap();
+        while !token.is_cancelled() {
+            std::thread::yield_now();
+        }
+        stopped_tx.send(()).unwrap();
+    })
+    .unwrap();
+
+    started_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    token.cancel();
+    stopped_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    pool.wait_idle();
+    let stats = pool.stats();
+    assert_eq!((stats.completed, stats.cancelled), (1, 0));
+}
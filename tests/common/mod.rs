@@ -0,0 +1,65 @@
+//! Helpers shared by the integration tests. Each test file uses only some of them.
+#![allow(dead_code)]
+
+use std::{sync::mpsc::{self, Receiver, Sender}, thread, time::{Duration, Instant}};
+use server_rs::ThreadPool;
+
+/// How long a test waits for something it expects to happen before failing.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A job holding a worker busy until the test lets it go, by calling `release` or dropping it.
+/// 
+/// ## Fields
+/// - `started`: Receives once the job has started.
+/// - `release`: Dropped to let the job finish.
+pub struct Held {
+    started: Receiver<()>,
+    release: Sender<()>,
+}
+
+impl Held {
+    /// Wait for the job to start, failing the test if it takes too long.
+    pub fn wait_started(&self) {
+        self.started.recv_timeout(TIMEOUT).expect("the holding job never started");
+    }
+
+    /// Let the job finish.
+    pub fn release(self) {}
+}
+
+/// Create a job that reports when it starts and then holds its worker until released.
+/// 
+/// ## Returns
+/// The handle to wait for and release the job with, and the job to execute however the
+/// test needs to.
+pub fn holding_job() -> (Held, impl FnOnce() + Send + 'static) {
+    let (started_tx, started) = mpsc::channel();
+    let (release, release_rx) = mpsc::channel::<()>();
+    let job = move || {
+        let _ = started_tx.send(());
+        let _ = release_rx.recv();
+    };
+    (Held { started, release }, job)
+}
+
+/// Hold one of the pool's workers with a job, and wait for it to start.
+pub fn hold_worker(pool: &ThreadPool) -> Held {
+    let (held, job) = holding_job();
+    pool.execute(job).unwrap();
+    held.wait_started();
+    held
+}
+
+/// Wait until `done` returns `true`, failing the test with `what` if it takes too long.
+pub fn wait_until(what: &str, mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + TIMEOUT;
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {what}");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Wait until the pool has `queued` jobs waiting, failing the test if it takes too long.
+pub fn wait_for_queued(pool: &ThreadPool, queued: usize) {
+    wait_until(&format!("{queued} queued jobs"), || pool.stats().queued == queued);
+}
@@ -0,0 +1,40 @@
+use std::sync::{Arc, Mutex};
+use server_rs::{DeterministicExecutor, Executor};
+
+const HANDLERS: usize = 8;
+
+/// Run handler jobs on an executor seeded with `seed`, each of which executes a follow-up
+/// job as a connection handler hands off its response, and return the order they ran in.
+fn run_order(seed: u64) -> Vec<String> {
+    let executor = Arc::new(DeterministicExecutor::seeded(seed));
+    let order = Arc::new(Mutex::new(Vec::new()));
+
+    for handler in 0..HANDLERS {
+        let (inner, order) = (Arc::clone(&executor), Arc::clone(&order));
+        executor
+            .execute(move || {
+                order.lock().unwrap().push(format!("handle {handler}"));
+                let order = Arc::clone(&order);
+                inner.execute(move || order.lock().unwrap().push(format!("respond {handler}"))).unwrap();
+            })
+            .unwrap();
+    }
+
+    assert_eq!(executor.run_until_idle(), HANDLERS * 2);
+    let order = order.lock().unwrap().clone();
+    order
+}
+
+#[test]
+fn same_seed_runs_jobs_in_the_same_order() {
+    for seed in [0, 1, 42, u64::MAX] {
+        let order = run_order(seed);
+        assert_eq!(order.len(), HANDLERS * 2);
+        assert_eq!(order, run_order(seed), "seed {seed} gave two different orders");
+    }
+}
+
+#[test]
+fn different_seeds_run_jobs_in_different_orders() {
+    assert_ne!(run_order(1), run_order(2));
+}
@@ -0,0 +1,71 @@
+use std::{thread, time::Duration};
+use server_rs::{PoolCreationError, ThreadPool};
+
+mod common;
+use common::{hold_worker, wait_for_queued, wait_until};
+
+/// The number of workers the pool has, busy or not.
+fn live(pool: &ThreadPool) -> usize {
+    let stats = pool.stats();
+    stats.idle + stats.running
+}
+
+#[test]
+fn elastic_pool_grows_to_max_threads_and_queues_the_rest() {
+    let pool = ThreadPool::build_elastic(1, 3, Duration::from_secs(60)).unwrap();
+    assert_eq!(live(&pool), 1);
+
+    // Each job only starts if a worker was added for it.
+    let held: Vec<_> = (0..3).map(|_| hold_worker(&pool)).collect();
+    assert_eq!(live(&pool), 3);
+
+    pool.execute(|| {}).unwrap();
+    wait_for_queued(&pool, 1);
+    assert_eq!(live(&pool), 3);
+
+    drop(held);
+    pool.wait_idle();
+    assert_eq!(pool.stats().completed, 4);
+}
+
+#[test]
+fn elastic_pool_retires_idle_workers_after_keep_alive() {
+    let pool = ThreadPool::build_elastic(1, 3, Duration::from_millis(20)).unwrap();
+    let held: Vec<_> = (0..3).map(|_| hold_worker(&pool)).collect();
+    assert_eq!(live(&pool), 3);
+
+    drop(held);
+    wait_until("the surplus workers to retire", || live(&pool) == 1);
+
+    // The worker left at `min_threads` stays on, however long it sits idle.
+    thread::sleep(Duration::from_millis(60));
+    assert_eq!(live(&pool), 1);
+    pool.execute(|| {}).unwrap();
+    pool.wait_idle();
+}
+
+#[test]
+fn resize_starts_workers_straight_away_and_retires_them_once_done() {
+    let pool = ThreadPool::build(1).unwrap();
+
+    pool.resize(3).unwrap();
+    assert_eq!(live(&pool), 3);
+    let held: Vec<_> = (0..3).map(|_| hold_worker(&pool)).collect();
+
+    // Busy workers finish their job before retiring.
+    pool.resize(1).unwrap();
+    assert_eq!(pool.stats().running, 3);
+
+    drop(held);
+    wait_until("the pool to shrink", || live(&pool) == 1);
+    pool.execute(|| {}).unwrap();
+    pool.wait_idle();
+    assert_eq!(live(&pool), 1);
+}
+
+#[test]
+fn resize_to_zero_is_rejected() {
+    let pool = ThreadPool::build(2).unwrap();
+    assert!(matches!(pool.resize(0), Err(PoolCreationError::ZeroSize)));
+    assert_eq!(live(&pool), 2);
+}
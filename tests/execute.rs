@@ -0,0 +1,69 @@
+use std::{cell::RefCell, sync::{mpsc::{self, Receiver}, Mutex}};
+use server_rs::{ExecuteError, ThreadPool};
+
+mod common;
+use common::{wait_until, TIMEOUT};
+
+/// Blocks the thread it belongs to from exiting until its sender is dropped.
+struct Stall(Receiver<()>);
+
+impl Drop for Stall {
+    fn drop(&mut self) {
+        let _ = self.0.recv();
+    }
+}
+
+thread_local! {
+    static STALL: RefCell<Option<Stall>> = const { RefCell::new(None) };
+}
+
+#[test]
+fn pool_without_live_workers_hands_jobs_back() {
+    let (release, stall) = mpsc::channel::<()>();
+    let stall = Mutex::new(Some(stall));
+
+    // The only worker dies as it starts, but can't finish exiting until released, so the
+    // supervisor can't start its replacement in the meantime.
+    let pool = ThreadPool::builder()
+        .num_threads(1)
+        .on_thread_start(move |_| {
+            let stall = stall.lock().unwrap().take();
+            if let Some(stall) = stall {
+                STALL.with(|slot| *slot.borrow_mut() = Some(Stall(stall)));
+                panic!("worker fails to start");
+            }
+        })
+        .build()
+        .unwrap();
+    wait_until("the worker to die", || pool.stats().idle == 0);
+
+    let (done_tx, done_rx) = mpsc::channel();
+    let rejected = match pool.execute(move || done_tx.send(()).unwrap()) {
+        Err(err @ ExecuteError::NoWorkers(_)) => {
+            assert_eq!(err.to_string(), "thread pool has no live workers");
+            err.into_inner()
+        },
+        other => panic!("expected no live workers, got {other:?}"),
+    };
+    assert_eq!(pool.stats().rejected, 1);
+
+    drop(release);
+    wait_until("a replacement worker", || pool.stats().idle == 1);
+    pool.execute(rejected).unwrap();
+    done_rx.recv_timeout(TIMEOUT).unwrap();
+}
+
+#[test]
+fn every_error_hands_back_its_job() {
+    let errors = [
+        (ExecuteError::Shutdown("first job"), "Shutdown(..)", "thread pool has been shut down"),
+        (ExecuteError::NoWorkers("second job"), "NoWorkers(..)", "thread pool has no live workers"),
+        (ExecuteError::QueueFull("third job"), "QueueFull(..)", "thread pool queue is full"),
+    ];
+
+    for ((err, debug, display), job) in errors.into_iter().zip(["first job", "second job", "third job"]) {
+        assert_eq!(format!("{err:?}"), debug);
+        assert_eq!(err.to_string(), display);
+        assert_eq!(err.into_inner(), job);
+    }
+}
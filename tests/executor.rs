@@ -0,0 +1,37 @@
+use std::{thread, time::Duration};
+use server_rs::{DeterministicExecutor, Executor, InlineExecutor, ThreadPerJob, ThreadPool};
+
+const JOBS: u64 = 4;
+
+/// Execute jobs that may still be running or queued when `shutdown` is called, and check that
+/// the report's stats count every one of them as completed.
+fn check_final_stats(executor: impl Executor) {
+    for _ in 0..JOBS {
+        executor.execute(|| thread::sleep(Duration::from_millis(20))).unwrap();
+    }
+
+    let report = executor.shutdown(Duration::from_secs(5));
+    assert!(report.is_complete());
+    assert_eq!(report.stats.completed, JOBS);
+    assert_eq!(report.stats.running, 0);
+}
+
+#[test]
+fn thread_pool_reports_final_stats_on_shutdown() {
+    check_final_stats(ThreadPool::build(2).unwrap());
+}
+
+#[test]
+fn thread_per_job_reports_final_stats_on_shutdown() {
+    check_final_stats(ThreadPerJob::new());
+}
+
+#[test]
+fn inline_executor_reports_final_stats_on_shutdown() {
+    check_final_stats(InlineExecutor::new());
+}
+
+#[test]
+fn deterministic_executor_reports_final_stats_on_shutdown() {
+    check_final_stats(DeterministicExecutor::seeded(1));
+}
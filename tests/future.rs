@@ -0,0 +1,118 @@
+use std::{future::Future, pin::Pin, sync::{mpsc, Arc, Mutex}, task::{Context, Poll, Waker}, time::Duration};
+use server_rs::{block_on, JoinError, ThreadPool};
+
+/// The state shared by a `Oneshot` and its sender.
+struct Slot<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// A future that finishes with the value sent through `send`, from whichever thread sends it.
+struct Oneshot<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> Future for Oneshot<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.value.take() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+/// Create a `Oneshot` and the function that completes it.
+fn oneshot<T: Send + 'static>() -> (Oneshot<T>, impl FnOnce(T) + Send + 'static) {
+    let slot = Arc::new(Mutex::new(Slot { value: None, waker: None }));
+    let sender = Arc::clone(&slot);
+    let send = move |value| {
+        let waker = {
+            let mut slot = sender.lock().unwrap();
+            slot.value = Some(value);
+            slot.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    };
+    (Oneshot { slot }, send)
+}
+
+/// A future that hands its waker out on its first poll, then stays pending until it is
+/// polled again.
+struct HandsOutWaker {
+    waker: Option<mpsc::Sender<Waker>>,
+}
+
+impl Future for HandsOutWaker {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        match self.waker.take() {
+            Some(sender) => {
+                sender.send(cx.waker().clone()).unwrap();
+                Poll::Pending
+            },
+            None => Poll::Ready(()),
+        }
+    }
+}
+
+#[test]
+fn spawned_future_resumes_when_woken_from_another_job() {
+    let pool = ThreadPool::build(2).unwrap();
+    let (value, send) = oneshot();
+    let handle = pool.spawn_future(async move { value.await * 2 });
+
+    pool.execute(move || send(21)).unwrap();
+    assert_eq!(handle.join().unwrap(), 42);
+}
+
+#[test]
+fn panic_in_a_spawned_future_is_returned_from_its_handle() {
+    let pool = ThreadPool::build(1).unwrap();
+    let handle = pool.spawn_future(async { panic!("future fails") });
+    let payload = handle.join().unwrap_err().into_panic().unwrap();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"future fails"));
+}
+
+#[test]
+fn block_on_inside_a_job_on_a_one_thread_pool_does_not_deadlock() {
+    let pool = Arc::new(ThreadPool::build(1).unwrap());
+    let (done_tx, done_rx) = mpsc::channel();
+
+    let inner = Arc::clone(&pool);
+    pool.execute(move || {
+        // The future can only finish once a job queued behind this one has run.
+        let (value, send) = oneshot();
+        inner.execute(move || send(21)).unwrap();
+        let value = block_on(async { value.await * 2 });
+
+        done_tx.send(value).unwrap();
+    })
+    .unwrap();
+
+    assert_eq!(done_rx.recv_timeout(Duration::from_secs(5)).unwrap(), 42);
+}
+
+#[test]
+fn future_woken_after_the_pool_has_gone_is_lost() {
+    let pool = ThreadPool::build(1).unwrap();
+    let (waker_tx, waker_rx) = mpsc::channel();
+    let mut handle = pool.spawn_future(HandsOutWaker { waker: Some(waker_tx) });
+    let waker = waker_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+    // Keep the waker alive, so only abandoning the future lets the handle see it is lost.
+    drop(pool);
+    waker.wake_by_ref();
+
+    let result = handle.join_timeout(Duration::from_secs(5)).expect("future still waiting after the pool went");
+    assert!(matches!(result, Err(JoinError::Lost)));
+    drop(waker);
+}
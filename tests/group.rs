@@ -0,0 +1,94 @@
+mod common;
+
+use std::{panic, sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc}, time::Duration};
+use server_rs::{JobGroup, ThreadPool};
+
+use common::hold_worker;
+
+#[test]
+fn wait_idle_waits_for_jobs_queued_by_jobs() {
+    let pool = Arc::new(ThreadPool::build(2).unwrap());
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    for _ in 0..10 {
+        let (inner, runs) = (Arc::downgrade(&pool), Arc::clone(&runs));
+        pool.execute(move || {
+            runs.fetch_add(1, Ordering::SeqCst);
+            if let Some(inner) = inner.upgrade() {
+                let runs = Arc::clone(&runs);
+                inner.execute(move || {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                })
+                .unwrap();
+            }
+        })
+        .unwrap();
+    }
+
+    pool.wait_idle();
+    assert_eq!(runs.load(Ordering::SeqCst), 20);
+}
+
+#[test]
+fn wait_idle_timeout_gives_up_on_a_stuck_job() {
+    let pool = ThreadPool::build(1).unwrap();
+    let held = hold_worker(&pool);
+
+    assert!(!pool.wait_idle_timeout(Duration::from_millis(50)));
+    held.release();
+    assert!(pool.wait_idle_timeout(Duration::from_secs(5)));
+}
+
+#[test]
+#[should_panic(expected = "wait for itself")]
+fn wait_idle_from_a_job_on_the_same_pool_panics() {
+    let pool = Arc::new(ThreadPool::build(1).unwrap());
+    let inner = Arc::clone(&pool);
+    let handle = pool.spawn(move || inner.wait_idle());
+    if let Err(err) = handle.join() {
+        panic::resume_unwind(err.into_panic().unwrap());
+    }
+}
+
+#[test]
+fn group_waits_only_for_its_own_jobs() {
+    let pool = ThreadPool::build(2).unwrap();
+
+    // A job outside the group holds one worker for the whole test.
+    let held = hold_worker(&pool);
+
+    let group = JobGroup::new();
+    let runs = Arc::new(AtomicUsize::new(0));
+    for _ in 0..5 {
+        let runs = Arc::clone(&runs);
+        pool.execute_in_group(&group, move || {
+            runs.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+    }
+
+    assert!(group.wait_timeout(Duration::from_secs(5)));
+    assert_eq!(group.pending(), 0);
+    assert_eq!(runs.load(Ordering::SeqCst), 5);
+    held.release();
+}
+
+#[test]
+fn group_wait_inside_a_job_on_a_one_thread_pool_does_not_deadlock() {
+    let pool = Arc::new(ThreadPool::build(1).unwrap());
+    let (done_tx, done_rx) = mpsc::channel();
+
+    let inner = Arc::clone(&pool);
+    pool.execute(move || {
+        let group = JobGroup::new();
+        for _ in 0..3 {
+            inner.execute_in_group(&group, || {}).unwrap();
+        }
+        group.wait();
+
+        done_tx.send(group.pending()).unwrap();
+    })
+    .unwrap();
+
+    assert_eq!(done_rx.recv_timeout(Duration::from_secs(5)).unwrap(), 0);
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+use server_rs::{panic_message, JoinError, RejectionPolicy, ThreadPool};
+
+mod common;
+use common::{hold_worker, holding_job, wait_for_queued, TIMEOUT};
+
+#[test]
+fn join_returns_the_jobs_result() {
+    let pool = ThreadPool::build(2).unwrap();
+    let handles: Vec<_> = (0..8).map(|item| pool.spawn(move || item * item)).collect();
+    let results: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+    assert_eq!(results, [0, 1, 4, 9, 16, 25, 36, 49]);
+}
+
+#[test]
+fn try_join_and_join_timeout_wait_for_the_job_to_finish() {
+    let pool = ThreadPool::build(1).unwrap();
+    let held = hold_worker(&pool);
+    let mut handle = pool.spawn(|| "done");
+
+    assert!(handle.try_join().is_none());
+    assert!(handle.join_timeout(Duration::from_millis(20)).is_none());
+
+    held.release();
+    assert_eq!(handle.join_timeout(TIMEOUT).unwrap().unwrap(), "done");
+
+    // The result can only be taken once.
+    assert!(matches!(handle.try_join(), Some(Err(JoinError::Lost))));
+    assert!(matches!(handle.join_timeout(TIMEOUT), Some(Err(JoinError::Lost))));
+}
+
+#[test]
+fn join_passes_on_the_jobs_panic() {
+    let pool = ThreadPool::build(1).unwrap();
+    let handle = pool.spawn(|| -> usize { panic!("job fails") });
+
+    let err = handle.join().unwrap_err();
+    assert_eq!(err.to_string(), "job panicked: job fails");
+    let payload = err.into_panic().unwrap();
+    assert_eq!(panic_message(&*payload), "job fails");
+
+    // A panic caught for the handle isn't one the pool counts, and the worker carries on.
+    pool.wait_idle();
+    assert_eq!(pool.stats().panicked, 0);
+    assert_eq!(pool.spawn(|| 1).join().unwrap(), 1);
+}
+
+#[test]
+fn join_reports_a_rejected_job_as_lost() {
+    let pool = ThreadPool::build_bounded(1, 1, RejectionPolicy::Abort).unwrap();
+    let held = hold_worker(&pool);
+    let (queued, job) = holding_job();
+    pool.execute(job).unwrap();
+    wait_for_queued(&pool, 1);
+
+    let err = pool.spawn(|| 1).join().unwrap_err();
+    assert!(matches!(err, JoinError::Lost));
+    assert!(err.into_panic().is_none());
+
+    held.release();
+    queued.release();
+}
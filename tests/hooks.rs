@@ -0,0 +1,107 @@
+use std::{hint, sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc, Mutex}, thread, time::Duration};
+use server_rs::ThreadPool;
+
+#[test]
+fn panicking_before_job_hook_drops_the_job_and_leaves_the_pool_usable() {
+    let panicked = Arc::new(AtomicBool::new(false));
+    let pool = {
+        let panicked = Arc::clone(&panicked);
+        ThreadPool::builder()
+            .num_threads(1)
+            .before_job(move |_| {
+                if !panicked.swap(true, Ordering::SeqCst) {
+                    panic!("before_job fails once");
+                }
+            })
+            .build()
+            .unwrap()
+    };
+    pool.set_panic_handler(|_, _| {});
+    let runs = Arc::new(AtomicUsize::new(0));
+    for _ in 0..2 {
+        let runs = Arc::clone(&runs);
+        pool.execute(move || {
+            runs.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+    }
+
+    assert!(pool.wait_idle_timeout(Duration::from_secs(2)));
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    let stats = pool.stats();
+    assert_eq!((stats.running, stats.idle), (0, 1));
+    assert_eq!((stats.completed, stats.panicked), (1, 1));
+}
+
+#[test]
+fn panicking_after_job_hook_counts_the_job_as_panicked() {
+    let reported = Arc::new(AtomicUsize::new(0));
+    let pool = ThreadPool::builder()
+        .num_threads(1)
+        .after_job(|_| panic!("after_job fails"))
+        .build()
+        .unwrap();
+    {
+        let reported = Arc::clone(&reported);
+        pool.set_panic_handler(move |_, _| {
+            reported.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+    pool.execute(|| {}).unwrap();
+    pool.execute(|| panic!("job fails")).unwrap();
+
+    assert!(pool.wait_idle_timeout(Duration::from_secs(2)));
+    let stats = pool.stats();
+    assert_eq!((stats.running, stats.idle), (0, 1));
+    assert_eq!((stats.completed, stats.panicked), (0, 2));
+    // Both hook panics and the job's own are reported.
+    assert_eq!(reported.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn workers_are_named_by_the_prefix_and_their_id() {
+    let name = || thread::current().name().map(str::to_owned);
+
+    let pool = ThreadPool::build(1).unwrap();
+    assert_eq!(pool.spawn(name).join().unwrap().as_deref(), Some("server-rs-worker-0"));
+
+    let pool = ThreadPool::builder().num_threads(1).thread_name("custom").build().unwrap();
+    assert_eq!(pool.spawn(name).join().unwrap().as_deref(), Some("custom-0"));
+}
+
+#[test]
+fn workers_get_the_stack_size_they_are_built_with() {
+    // Well past the standard library's default of 2 MiB, so this overflows without it.
+    let pool = ThreadPool::builder().num_threads(1).stack_size(32 << 20).build().unwrap();
+    let sum = pool.spawn(|| {
+        let buffer = [1u8; 8 << 20];
+        hint::black_box(&buffer).iter().map(|&byte| usize::from(byte)).sum::<usize>()
+    });
+    assert_eq!(sum.join().unwrap(), 8 << 20);
+}
+
+#[test]
+fn hooks_run_in_order_around_each_job() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let record = |event: &'static str| {
+        let events = Arc::clone(&events);
+        move |_: usize| events.lock().unwrap().push(event)
+    };
+
+    let pool = ThreadPool::builder()
+        .num_threads(1)
+        .on_thread_start(record("start"))
+        .before_job(record("before"))
+        .after_job(record("after"))
+        .on_thread_stop(record("stop"))
+        .build()
+        .unwrap();
+    for _ in 0..2 {
+        let events = Arc::clone(&events);
+        pool.execute(move || events.lock().unwrap().push("job")).unwrap();
+    }
+    drop(pool);
+
+    let events = events.lock().unwrap();
+    assert_eq!(*events, ["start", "before", "job", "after", "before", "job", "after", "stop"]);
+}
@@ -0,0 +1,85 @@
+use std::{panic::{self, AssertUnwindSafe}, sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc}, time::Duration};
+use server_rs::{RejectionPolicy, ThreadPool};
+
+#[test]
+fn join_returns_both_results() {
+    let pool = ThreadPool::build(2).unwrap();
+    let words = ["a", "b", "c"];
+    let (count, joined) = pool.join(|| words.len(), || words.concat());
+    assert_eq!((count, joined.as_str()), (3, "abc"));
+}
+
+#[test]
+fn map_keeps_the_order_of_the_items() {
+    let pool = ThreadPool::build(4).unwrap();
+    let offset = 10;
+    let results = pool.map(0..100, |item| item * 2 + offset);
+    assert_eq!(results, (0..100).map(|item| item * 2 + offset).collect::<Vec<_>>());
+}
+
+#[test]
+fn for_each_chunked_visits_every_item_once() {
+    let pool = ThreadPool::build(4).unwrap();
+    let items: Vec<usize> = (0..1000).collect();
+    let sum = AtomicUsize::new(0);
+    let visits = AtomicUsize::new(0);
+
+    pool.for_each_chunked(&items, 64, |item| {
+        sum.fetch_add(*item, Ordering::SeqCst);
+        visits.fetch_add(1, Ordering::SeqCst);
+    });
+
+    assert_eq!(visits.into_inner(), 1000);
+    assert_eq!(sum.into_inner(), 999 * 1000 / 2);
+}
+
+#[test]
+fn map_passes_on_a_panic_once_every_item_is_done() {
+    let pool = ThreadPool::build(2).unwrap();
+    let handled = AtomicUsize::new(0);
+
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        pool.map(0..10, |item| {
+            handled.fetch_add(1, Ordering::SeqCst);
+            assert_ne!(item, 3, "item 3 fails");
+        })
+    }));
+
+    assert!(outcome.is_err());
+    assert_eq!(handled.into_inner(), 10);
+}
+
+#[test]
+fn nested_map_inside_a_job_on_a_one_thread_pool_does_not_deadlock() {
+    let pool = Arc::new(ThreadPool::build(1).unwrap());
+    let (done_tx, done_rx) = mpsc::channel();
+
+    let inner = Arc::clone(&pool);
+    pool.execute(move || {
+        // Each item maps again, so every level waits on jobs queued behind it.
+        let sums = inner.map(0..4, |row| inner.map(0..4, |col| row * 4 + col).into_iter().sum::<usize>());
+        let (left, right) = inner.join(|| sums[..2].iter().sum::<usize>(), || sums[2..].iter().sum::<usize>());
+
+        done_tx.send((sums, left + right)).unwrap();
+    })
+    .unwrap();
+
+    let (sums, total) = done_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(sums, [6, 22, 38, 54]);
+    assert_eq!(total, 120);
+}
+
+#[test]
+fn nested_map_inside_a_job_on_a_full_blocking_pool_does_not_deadlock() {
+    let pool = Arc::new(ThreadPool::builder().num_threads(1).bounded(1, RejectionPolicy::Block).build().unwrap());
+    let (done_tx, done_rx) = mpsc::channel();
+
+    // The queue only has room for one job, and only this job's worker could make more.
+    let inner = Arc::clone(&pool);
+    pool.execute(move || {
+        done_tx.send(inner.map(0..4, |item| item * 2)).unwrap();
+    })
+    .unwrap();
+
+    assert_eq!(done_rx.recv_timeout(Duration::from_secs(5)).unwrap(), [0, 2, 4, 6]);
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use std::{sync::{atomic::{AtomicBool, Ordering}, mpsc, Arc, Mutex}, thread, time::Duration};
+use server_rs::{RejectionPolicy, Scheduler, ThreadPool};
+
+use common::{hold_worker, holding_job};
+
+const KEYS: usize = 8;
+const JOBS_PER_KEY: usize = 200;
+
+/// Submit interleaved jobs for several keys from several threads at once, and check that each
+/// key's jobs ran one at a time and in the order each thread submitted them.
+fn check_ordering(pool: ThreadPool) {
+    let pool = Arc::new(pool);
+    let running: Arc<Vec<AtomicBool>> = Arc::new((0..KEYS).map(|_| AtomicBool::new(false)).collect());
+    let seen: Arc<Vec<Mutex<Vec<usize>>>> = Arc::new((0..KEYS).map(|_| Mutex::new(Vec::new())).collect());
+
+    // One submitting thread per key pair, so keys contend with each other for the queue.
+    let submitters: Vec<_> = (0..KEYS / 2)
+        .map(|submitter| {
+            let (pool, running, seen) = (Arc::clone(&pool), Arc::clone(&running), Arc::clone(&seen));
+            thread::spawn(move || {
+                for seq in 0..JOBS_PER_KEY {
+                    for key in [submitter * 2, submitter * 2 + 1] {
+                        let (running, seen) = (Arc::clone(&running), Arc::clone(&seen));
+                        pool.execute_keyed(&key, move || {
+                            assert!(!running[key].swap(true, Ordering::AcqRel), "two jobs for key {key} ran at once");
+                            seen[key].lock().unwrap().push(seq);
+                            thread::yield_now();
+                            running[key].store(false, Ordering::Release);
+                        })
+                        .unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+    for submitter in submitters {
+        submitter.join().unwrap();
+    }
+
+    // Dropping the pool waits for every job, including those still waiting in a lane.
+    drop(Arc::into_inner(pool).unwrap());
+
+    for (key, seen) in seen.iter().enumerate() {
+        let seen = seen.lock().unwrap();
+        assert_eq!(*seen, (0..JOBS_PER_KEY).collect::<Vec<_>>(), "jobs for key {key} ran out of order");
+    }
+    assert_eq!(running.iter().filter(|running| running.load(Ordering::Acquire)).count(), 0);
+}
+
+#[test]
+fn keyed_jobs_run_in_order_on_channel_scheduler() {
+    check_ordering(ThreadPool::build_with_scheduler(4, Scheduler::Channel).unwrap());
+}
+
+#[test]
+fn keyed_jobs_run_in_order_on_lock_free_scheduler() {
+    check_ordering(ThreadPool::build_with_scheduler(4, Scheduler::LockFree).unwrap());
+}
+
+#[test]
+fn keyed_jobs_run_in_order_on_work_stealing_scheduler() {
+    check_ordering(ThreadPool::build_with_scheduler(4, Scheduler::WorkStealing).unwrap());
+}
+
+#[test]
+fn different_keys_run_in_parallel() {
+    let pool = ThreadPool::build(2).unwrap();
+    let (a_tx, a_rx) = mpsc::channel();
+    let (b_tx, b_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel();
+
+    // Each job waits for the other, so they can only both finish if they run at the same time.
+    let a_done = done_tx.clone();
+    pool.execute_keyed("a", move || {
+        a_tx.send(()).unwrap();
+        a_done.send(b_rx.recv_timeout(Duration::from_secs(5)).is_ok()).unwrap();
+    })
+    .unwrap();
+    pool.execute_keyed("b", move || {
+        b_tx.send(()).unwrap();
+        done_tx.send(a_rx.recv_timeout(Duration::from_secs(5)).is_ok()).unwrap();
+    })
+    .unwrap();
+
+    for _ in 0..2 {
+        assert!(done_rx.recv_timeout(Duration::from_secs(10)).unwrap());
+    }
+}
+
+#[test]
+fn panicking_job_does_not_stall_its_key() {
+    let pool = ThreadPool::build(1).unwrap();
+    pool.set_panic_handler(|_, _| {});
+    let (tx, rx) = mpsc::channel();
+
+    pool.execute_keyed("key", || panic!("first job fails")).unwrap();
+    pool.execute_keyed("key", move || tx.send(()).unwrap()).unwrap();
+
+    rx.recv_timeout(Duration::from_secs(5)).unwrap();
+}
+
+#[test]
+fn jobs_waiting_behind_their_key_are_not_rejected() {
+    let pool = ThreadPool::build_bounded(1, 1, RejectionPolicy::Abort).unwrap();
+    let (done_tx, done_rx) = mpsc::channel();
+
+    // Hold the only worker so the first keyed job stays queued and fills the queue.
+    let held = hold_worker(&pool);
+
+    for seq in 0..5 {
+        let done_tx = done_tx.clone();
+        assert!(pool.execute_keyed("key", move || done_tx.send(seq).unwrap()).is_ok());
+    }
+    // A job for another key still has to fit in the queue.
+    assert!(pool.execute_keyed("other", || {}).is_err());
+
+    held.release();
+    let order: Vec<_> = (0..5).map(|_| done_rx.recv_timeout(Duration::from_secs(5)).unwrap()).collect();
+    assert_eq!(order, [0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn shutdown_now_hands_back_jobs_waiting_behind_their_key() {
+    let pool = ThreadPool::build(1).unwrap();
+
+    let (held, job) = holding_job();
+    pool.execute_keyed("key", job).unwrap();
+    held.wait_started();
+    for _ in 0..3 {
+        pool.execute_keyed("key", || {}).unwrap();
+    }
+
+    assert_eq!(pool.shutdown_now().len(), 3);
+    held.release();
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+use server_rs::{logging::{self, Level, Logger, MemorySink}, ThreadPool};
+
+/// Run one job on a one-worker pool and drop it, logging to `logger` if one is given and to
+/// the global logger otherwise.
+fn run_one_job(logger: Option<Logger>) {
+    let mut builder = ThreadPool::builder().num_threads(1);
+    if let Some(logger) = logger {
+        builder = builder.logger(logger);
+    }
+    let pool = builder.build().unwrap();
+    pool.execute(|| {}).unwrap();
+    pool.wait_idle();
+}
+
+#[test]
+fn filter_sets_the_default_and_per_component_levels() {
+    let logger = Logger::new(Arc::new(MemorySink::new())).with_filter("warn, worker=debug ,supervisor=off").unwrap();
+
+    assert!(logger.enabled(Level::Warn, "pool"));
+    assert!(!logger.enabled(Level::Info, "pool"));
+    assert!(logger.enabled(Level::Debug, "worker"));
+    assert!(!logger.enabled(Level::Trace, "worker"));
+    assert!(!logger.enabled(Level::Error, "supervisor"));
+    assert_eq!(logger.max_level(), Some(Level::Debug));
+
+    assert!(Logger::new(Arc::new(MemorySink::new())).with_filter("pool=loud").is_err());
+}
+
+#[test]
+fn pool_logs_to_its_own_sink_at_each_components_level() {
+    let sink = Arc::new(MemorySink::new());
+    run_one_job(Some(Logger::new(Arc::<MemorySink>::clone(&sink)).with_component_level("worker", Some(Level::Debug))));
+
+    let events = sink.take();
+    let got_job = events.iter().find(|event| event.message == "got a job; executing").expect("no debug event from the worker");
+    assert_eq!((got_job.level, got_job.component, got_job.field("worker")), (Level::Debug, "worker", Some("0")));
+    assert!(events.iter().any(|event| event.component == "worker" && event.message == "shutting down"));
+    assert!(events.iter().all(|event| event.level <= Level::Info || event.component == "worker"));
+    assert!(sink.events().is_empty());
+
+    // Without the component's own level, its debug events are left out.
+    let sink = Arc::new(MemorySink::new());
+    run_one_job(Some(Logger::new(Arc::<MemorySink>::clone(&sink))));
+    assert!(sink.events().iter().all(|event| event.level <= Level::Info));
+}
+
+#[test]
+fn global_logger_skips_events_below_its_most_detailed_level() {
+    let sink = Arc::new(MemorySink::new());
+
+    logging::set_logger(Logger::new(Arc::<MemorySink>::clone(&sink)).with_level(Some(Level::Warn)));
+    run_one_job(None);
+    assert!(sink.take().is_empty());
+
+    // A component logged in more detail than the default still gets through.
+    logging::set_logger(Logger::new(Arc::<MemorySink>::clone(&sink)).with_level(None).with_component_level("worker", Some(Level::Debug)));
+    run_one_job(None);
+    let events = sink.take();
+    assert!(events.iter().any(|event| event.level == Level::Debug && event.message == "got a job; executing"));
+    assert!(events.iter().all(|event| event.component == "worker"));
+
+    logging::set_logger(Logger::new(Arc::<MemorySink>::clone(&sink)).with_level(None));
+    run_one_job(None);
+    assert!(sink.take().is_empty());
+}
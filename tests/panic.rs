@@ -0,0 +1,119 @@
+mod common;
+
+use std::{sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc}, time::Duration};
+use server_rs::{panic_message, Scheduler, ThreadPool};
+
+use common::{wait_until, TIMEOUT};
+
+/// Run panicking jobs alongside ordinary ones, and check that every ordinary job still runs,
+/// every panic reaches the handler and the pool keeps all its workers.
+fn check_panics_are_isolated(scheduler: Scheduler) {
+    let pool = ThreadPool::build_with_scheduler(2, scheduler).unwrap();
+    let (panic_tx, panic_rx) = mpsc::channel();
+    pool.set_panic_handler(move |id, payload| {
+        panic_tx.send((id, panic_message(payload).to_string())).unwrap();
+    });
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    for i in 0..10 {
+        pool.execute(move || panic!("job {i} fails")).unwrap();
+        let runs = Arc::clone(&runs);
+        pool.execute(move || {
+            runs.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+    }
+    pool.wait_idle();
+
+    assert_eq!(runs.load(Ordering::SeqCst), 10);
+    let mut messages: Vec<_> = panic_rx.try_iter().map(|(id, message)| {
+        assert!(id < 2, "panic reported for unknown worker {id}");
+        message
+    })
+    .collect();
+    messages.sort();
+    let mut expected: Vec<_> = (0..10).map(|i| format!("job {i} fails")).collect();
+    expected.sort();
+    assert_eq!(messages, expected);
+
+    let stats = pool.stats();
+    assert_eq!((stats.completed, stats.panicked, stats.idle), (10, 10, 2));
+}
+
+#[test]
+fn panicking_jobs_leave_the_channel_pool_usable() {
+    check_panics_are_isolated(Scheduler::Channel);
+}
+
+#[test]
+fn panicking_jobs_leave_the_lock_free_pool_usable() {
+    check_panics_are_isolated(Scheduler::LockFree);
+}
+
+#[test]
+fn panicking_jobs_leave_the_work_stealing_pool_usable() {
+    check_panics_are_isolated(Scheduler::WorkStealing);
+}
+
+#[test]
+fn worker_that_dies_is_replaced() {
+    let started = Arc::new(AtomicUsize::new(0));
+    let pool = {
+        let started = Arc::clone(&started);
+        ThreadPool::builder()
+            .num_threads(2)
+            .on_thread_start(move |_| {
+                // The first worker to start dies, outside any job.
+                if started.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("worker fails to start");
+                }
+            })
+            .build()
+            .unwrap()
+    };
+    wait_until("a replacement worker", || started.load(Ordering::SeqCst) == 3);
+
+    // Both workers are back: two jobs that each wait for the other can only finish together.
+    let (a_tx, a_rx) = mpsc::channel();
+    let (b_tx, b_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel();
+    let a_done = done_tx.clone();
+    pool.execute(move || {
+        a_tx.send(()).unwrap();
+        a_done.send(b_rx.recv_timeout(TIMEOUT).is_ok()).unwrap();
+    })
+    .unwrap();
+    pool.execute(move || {
+        b_tx.send(()).unwrap();
+        done_tx.send(a_rx.recv_timeout(TIMEOUT).is_ok()).unwrap();
+    })
+    .unwrap();
+    for _ in 0..2 {
+        assert!(done_rx.recv_timeout(TIMEOUT).unwrap());
+    }
+    pool.wait_idle();
+    assert_eq!(pool.stats().idle, 2);
+}
+
+#[test]
+fn periodic_job_carries_on_after_a_run_panics_with_its_lock_held() {
+    let pool = ThreadPool::build(1).unwrap();
+    pool.set_panic_handler(|_, _| {});
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    // Each run holds a lock on the job while it runs, so the panic poisons it.
+    let job = {
+        let runs = Arc::clone(&runs);
+        pool.execute_every(Duration::from_millis(5), move || {
+            if runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("first run fails");
+            }
+        })
+        .unwrap()
+    };
+    wait_until("runs after the panic", || runs.load(Ordering::SeqCst) >= 3);
+    job.cancel();
+    pool.wait_idle();
+
+    assert_eq!(pool.stats().panicked, 1);
+}
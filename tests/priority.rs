@@ -0,0 +1,106 @@
+use std::{sync::{Arc, Mutex}, thread, time::Duration};
+use server_rs::{Priority, QueueDepths, Scheduler, ThreadPool};
+
+mod common;
+use common::{hold_worker, wait_for_queued};
+
+/// Build a one-thread pool, so that queued jobs run one at a time in the order it picks.
+fn pool_with(scheduler: Scheduler, aging: Duration) -> ThreadPool {
+    ThreadPool::builder().num_threads(1).scheduler(scheduler).aging(aging).build().unwrap()
+}
+
+/// Queue a job that records `name` once it runs.
+fn queue(pool: &ThreadPool, ran: &Arc<Mutex<Vec<&'static str>>>, priority: Priority, name: &'static str) {
+    let ran = Arc::clone(ran);
+    pool.execute_with_priority(priority, move || ran.lock().unwrap().push(name)).unwrap();
+}
+
+fn check_higher_priorities_run_first(scheduler: Scheduler) {
+    let pool = pool_with(scheduler, Duration::from_secs(60));
+    let ran = Arc::new(Mutex::new(Vec::new()));
+    let held = hold_worker(&pool);
+
+    queue(&pool, &ran, Priority::Low, "low 1");
+    queue(&pool, &ran, Priority::Normal, "normal 1");
+    queue(&pool, &ran, Priority::Low, "low 2");
+    queue(&pool, &ran, Priority::High, "high 1");
+    queue(&pool, &ran, Priority::Normal, "normal 2");
+    queue(&pool, &ran, Priority::High, "high 2");
+    wait_for_queued(&pool, 6);
+
+    held.release();
+    pool.wait_idle();
+    assert_eq!(*ran.lock().unwrap(), ["high 1", "high 2", "normal 1", "normal 2", "low 1", "low 2"]);
+}
+
+#[test]
+fn higher_priorities_run_first_channel() {
+    check_higher_priorities_run_first(Scheduler::Channel);
+}
+
+#[test]
+fn higher_priorities_run_first_lock_free() {
+    check_higher_priorities_run_first(Scheduler::LockFree);
+}
+
+fn check_waiting_jobs_are_promoted(scheduler: Scheduler) {
+    let pool = pool_with(scheduler, Duration::from_millis(20));
+    let ran = Arc::new(Mutex::new(Vec::new()));
+    let held = hold_worker(&pool);
+
+    // Waiting for two agings and more takes the low job up to high, ahead of a new high job
+    // since it has waited longer.
+    queue(&pool, &ran, Priority::Low, "low");
+    thread::sleep(Duration::from_millis(60));
+    queue(&pool, &ran, Priority::High, "high");
+    wait_for_queued(&pool, 2);
+
+    held.release();
+    pool.wait_idle();
+    assert_eq!(*ran.lock().unwrap(), ["low", "high"]);
+}
+
+#[test]
+fn waiting_jobs_are_promoted_channel() {
+    check_waiting_jobs_are_promoted(Scheduler::Channel);
+}
+
+#[test]
+fn waiting_jobs_are_promoted_lock_free() {
+    check_waiting_jobs_are_promoted(Scheduler::LockFree);
+}
+
+fn check_queue_depths_count_each_priority(scheduler: Scheduler) {
+    let pool = pool_with(scheduler, Duration::from_secs(60));
+    let ran = Arc::new(Mutex::new(Vec::new()));
+    let held = hold_worker(&pool);
+    assert_eq!(pool.queue_depths(), QueueDepths::default());
+
+    queue(&pool, &ran, Priority::High, "high");
+    for _ in 0..2 {
+        queue(&pool, &ran, Priority::Normal, "normal");
+    }
+    for _ in 0..3 {
+        queue(&pool, &ran, Priority::Low, "low");
+    }
+    wait_for_queued(&pool, 6);
+
+    let depths = pool.queue_depths();
+    assert_eq!(depths, QueueDepths { high: 1, normal: 2, low: 3 });
+    assert_eq!(depths.get(Priority::Normal), 2);
+    assert_eq!(depths.total(), 6);
+
+    held.release();
+    pool.wait_idle();
+    assert_eq!(pool.queue_depths(), QueueDepths::default());
+}
+
+#[test]
+fn queue_depths_count_each_priority_channel() {
+    check_queue_depths_count_each_priority(Scheduler::Channel);
+}
+
+#[test]
+fn queue_depths_count_each_priority_lock_free() {
+    check_queue_depths_count_each_priority(Scheduler::LockFree);
+}
@@ -0,0 +1,89 @@
+use std::{panic::{self, AssertUnwindSafe}, sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc}, time::Duration};
+use server_rs::{RejectionPolicy, ThreadPool};
+
+#[test]
+fn scoped_jobs_borrow_from_the_stack() {
+    let pool = ThreadPool::build(4).unwrap();
+    let items: Vec<usize> = (1..=100).collect();
+    let mut sums = [0; 4];
+
+    pool.scope(|scope| {
+        for (chunk, sum) in items.chunks(25).zip(&mut sums) {
+            scope.execute(move || *sum = chunk.iter().sum());
+        }
+    });
+
+    assert_eq!(sums, [325, 950, 1575, 2200]);
+}
+
+#[test]
+fn scope_waits_for_every_job_before_passing_on_a_panic() {
+    let pool = ThreadPool::build(2).unwrap();
+    let finished = AtomicUsize::new(0);
+
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        pool.scope(|scope| {
+            scope.execute(|| panic!("scoped job fails"));
+            for _ in 0..8 {
+                scope.execute(|| {
+                    finished.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        })
+    }));
+
+    assert!(outcome.is_err());
+    assert_eq!(finished.load(Ordering::SeqCst), 8);
+}
+
+#[test]
+fn scope_inside_a_job_on_a_one_thread_pool_does_not_deadlock() {
+    let pool = Arc::new(ThreadPool::build(1).unwrap());
+    let (done_tx, done_rx) = mpsc::channel();
+
+    let inner = Arc::clone(&pool);
+    pool.execute(move || {
+        let finished = AtomicUsize::new(0);
+        inner.scope(|scope| {
+            for _ in 0..4 {
+                scope.execute(|| {
+                    finished.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        done_tx.send(finished.into_inner()).unwrap();
+    })
+    .unwrap();
+
+    assert_eq!(done_rx.recv_timeout(Duration::from_secs(5)).unwrap(), 4);
+}
+
+#[test]
+fn nested_scopes_inside_a_job_on_a_full_blocking_pool_do_not_deadlock() {
+    let pool = Arc::new(ThreadPool::builder().num_threads(1).bounded(1, RejectionPolicy::Block).build().unwrap());
+    let (done_tx, done_rx) = mpsc::channel();
+
+    let inner = Arc::clone(&pool);
+    pool.execute(move || {
+        let finished = AtomicUsize::new(0);
+        inner.scope(|outer| {
+            for _ in 0..2 {
+                outer.execute(|| {
+                    inner.scope(|scope| {
+                        for _ in 0..2 {
+                            scope.execute(|| {
+                                finished.fetch_add(1, Ordering::SeqCst);
+                            });
+                        }
+                    });
+                });
+            }
+        });
+
+        done_tx.send(finished.into_inner()).unwrap();
+    })
+    .unwrap();
+
+    assert_eq!(done_rx.recv_timeout(Duration::from_secs(5)).unwrap(), 4);
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use std::{sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc}, time::{Duration, Instant}};
+use server_rs::ThreadPool;
+
+use common::{hold_worker, wait_until, TIMEOUT};
+
+#[test]
+fn shutdown_runs_every_queued_job() {
+    let pool = ThreadPool::build(2).unwrap();
+    let runs = Arc::new(AtomicUsize::new(0));
+    for _ in 0..50 {
+        let runs = Arc::clone(&runs);
+        pool.execute(move || {
+            runs.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+    }
+
+    let report = pool.shutdown(Duration::from_secs(5));
+    assert!(report.is_complete());
+    assert_eq!(runs.load(Ordering::SeqCst), 50);
+}
+
+#[test]
+fn shutdown_with_a_stuck_job_returns_at_its_timeout() {
+    let pool = ThreadPool::build(1).unwrap();
+    let held = hold_worker(&pool);
+    let runs = Arc::new(AtomicUsize::new(0));
+    for _ in 0..3 {
+        let runs = Arc::clone(&runs);
+        pool.execute(move || {
+            runs.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+    }
+
+    let started = Instant::now();
+    let report = pool.shutdown(Duration::from_millis(100));
+    assert!(started.elapsed() < Duration::from_secs(2), "shutdown took {:?}", started.elapsed());
+
+    assert!(!report.is_complete());
+    assert_eq!(report.busy_workers.len(), 1);
+    assert_eq!(report.discarded, 3);
+    assert_eq!(report.stats.completed, 0);
+
+    // The stuck job carries on once released, but the jobs behind it were dropped.
+    held.release();
+    assert_eq!(runs.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn shutdown_now_hands_back_queued_jobs_without_waiting() {
+    let pool = ThreadPool::build(1).unwrap();
+    let held = hold_worker(&pool);
+    let runs = Arc::new(AtomicUsize::new(0));
+    for _ in 0..3 {
+        let runs = Arc::clone(&runs);
+        pool.execute(move || {
+            runs.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+    }
+
+    let started = Instant::now();
+    let jobs = pool.shutdown_now();
+    assert!(started.elapsed() < Duration::from_secs(2), "shutdown_now took {:?}", started.elapsed());
+    assert_eq!(jobs.len(), 3);
+    assert_eq!(runs.load(Ordering::SeqCst), 0);
+
+    // The jobs handed back can still be run by the caller.
+    for job in jobs {
+        job();
+    }
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+    held.release();
+}
+
+#[test]
+fn dropping_the_last_handle_inside_a_job_shuts_the_pool_down() {
+    let stopped = Arc::new(AtomicUsize::new(0));
+    let pool = {
+        let stopped = Arc::clone(&stopped);
+        Arc::new(
+            ThreadPool::builder()
+                .num_threads(2)
+                .on_thread_stop(move |_| {
+                    stopped.fetch_add(1, Ordering::SeqCst);
+                })
+                .build()
+                .unwrap(),
+        )
+    };
+    let (go_tx, go_rx) = mpsc::channel::<()>();
+    let (done_tx, done_rx) = mpsc::channel();
+
+    let inner = Arc::clone(&pool);
+    pool.execute(move || {
+        let _ = go_rx.recv();
+        // By now this is the pool's last handle, so the pool is dropped on its own worker.
+        drop(inner);
+        done_tx.send(()).unwrap();
+    })
+    .unwrap();
+    drop(pool);
+    drop(go_tx);
+
+    done_rx.recv_timeout(TIMEOUT).unwrap();
+    wait_until("both workers to stop", || stopped.load(Ordering::SeqCst) == 2);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use std::{sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc}, thread};
+use server_rs::{RejectionPolicy, ThreadPool};
+
+use common::holding_job;
+
+/// A worker state that counts how many states have been created and dropped.
+struct Counted {
+    dropped: Arc<AtomicUsize>,
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        self.dropped.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn states_are_dropped_when_the_pool_drops() {
+    let created = Arc::new(AtomicUsize::new(0));
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let pool = {
+        let (created, dropped) = (Arc::clone(&created), Arc::clone(&dropped));
+        ThreadPool::with_state(3, move |_| {
+            created.fetch_add(1, Ordering::SeqCst);
+            Counted { dropped: Arc::clone(&dropped) }
+        })
+        .unwrap()
+    };
+    for _ in 0..10 {
+        pool.execute(|_| {}).unwrap();
+    }
+
+    drop(pool);
+    assert_eq!(created.load(Ordering::SeqCst), 3);
+    assert_eq!(dropped.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn caller_runs_job_gets_a_state_that_is_not_kept() {
+    let created = Arc::new(AtomicUsize::new(0));
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let pool = {
+        let (created, dropped) = (Arc::clone(&created), Arc::clone(&dropped));
+        ThreadPool::builder()
+            .num_threads(1)
+            .bounded(1, RejectionPolicy::CallerRuns)
+            .build_with_state(move |_| {
+                created.fetch_add(1, Ordering::SeqCst);
+                Counted { dropped: Arc::clone(&dropped) }
+            })
+            .unwrap()
+    };
+
+    // Hold the only worker and fill the queue, so the next jobs run on this thread.
+    let (held, job) = holding_job();
+    pool.execute(move |_| job()).unwrap();
+    held.wait_started();
+    pool.execute(|_| {}).unwrap();
+
+    let caller = thread::current().id();
+    for run in 1..=2 {
+        let (ran_tx, ran_rx) = mpsc::channel();
+        pool.execute(move |_| ran_tx.send(thread::current().id()).unwrap()).unwrap();
+        assert_eq!(ran_rx.try_recv().unwrap(), caller);
+        assert_eq!(created.load(Ordering::SeqCst), 1 + run);
+        assert_eq!(dropped.load(Ordering::SeqCst), run);
+    }
+
+    held.release();
+    drop(pool);
+    assert_eq!(dropped.load(Ordering::SeqCst), 3);
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use std::time::Duration;
+use server_rs::{RejectionPolicy, ThreadPool};
+
+use common::hold_worker;
+
+#[test]
+fn stats_count_completed_panicked_and_rejected_jobs() {
+    let pool = ThreadPool::build_bounded(1, 1, RejectionPolicy::Abort).unwrap();
+    pool.set_panic_handler(|_, _| {});
+
+    // Hold the only worker and fill the queue, so the next job is rejected.
+    let held = hold_worker(&pool);
+    pool.execute(|| panic!("queued job fails")).unwrap();
+    assert!(pool.execute(|| {}).is_err());
+
+    let stats = pool.stats();
+    assert_eq!((stats.queued, stats.running, stats.idle), (1, 1, 0));
+    assert_eq!(stats.rejected, 1);
+
+    held.release();
+    pool.wait_idle();
+    for _ in 0..3 {
+        pool.execute(|| {}).unwrap();
+        pool.wait_idle();
+    }
+
+    let stats = pool.stats();
+    assert_eq!((stats.queued, stats.running, stats.idle), (0, 0, 1));
+    assert_eq!((stats.completed, stats.panicked, stats.rejected), (4, 1, 1));
+    assert_eq!(stats.queue_wait.count(), 5);
+    assert_eq!(stats.execution.count(), 5);
+}
+
+#[test]
+fn execution_histogram_records_how_long_jobs_ran() {
+    let pool = ThreadPool::build(1).unwrap();
+    pool.execute(|| std::thread::sleep(Duration::from_millis(20))).unwrap();
+    pool.wait_idle();
+
+    let execution = pool.stats().execution;
+    assert_eq!(execution.count(), 1);
+    assert!(execution.sum() >= Duration::from_millis(20));
+    assert!(execution.percentile(0.5).unwrap() >= Duration::from_millis(20));
+}
@@ -0,0 +1,77 @@
+use std::{sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc}, thread};
+use server_rs::{Scheduler, ThreadPool};
+
+mod common;
+use common::{wait_until, TIMEOUT};
+
+/// Build a work-stealing pool, shared so that its jobs can queue more jobs.
+fn work_stealing_pool(size: usize) -> Arc<ThreadPool> {
+    Arc::new(ThreadPool::build_with_scheduler(size, Scheduler::WorkStealing).unwrap())
+}
+
+#[test]
+fn every_job_runs_exactly_once() {
+    let pool = work_stealing_pool(4);
+    let ran = Arc::new(AtomicUsize::new(0));
+
+    for _ in 0..10_000 {
+        let ran = Arc::clone(&ran);
+        pool.execute(move || {
+            ran.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+    }
+
+    pool.wait_idle();
+    assert_eq!(ran.load(Ordering::SeqCst), 10_000);
+    assert_eq!(pool.stats().completed, 10_000);
+}
+
+#[test]
+fn idle_workers_steal_jobs_queued_by_a_busy_one() {
+    let pool = work_stealing_pool(4);
+    let (done_tx, done_rx) = mpsc::channel();
+
+    // The jobs go on the submitting worker's own deque, and it stays busy until they are
+    // done, so they only run if the other workers steal them.
+    let inner = Arc::clone(&pool);
+    pool.execute(move || {
+        let (ran_on_tx, ran_on_rx) = mpsc::channel();
+        for _ in 0..16 {
+            let ran_on_tx = ran_on_tx.clone();
+            inner.execute(move || ran_on_tx.send(thread::current().id()).unwrap()).unwrap();
+        }
+
+        let ran_on: Vec<_> = (0..16).map(|_| ran_on_rx.recv_timeout(TIMEOUT).unwrap()).collect();
+        done_tx.send(ran_on.iter().all(|&id| id != thread::current().id())).unwrap();
+    })
+    .unwrap();
+
+    assert!(done_rx.recv_timeout(TIMEOUT).unwrap());
+}
+
+#[test]
+fn dropping_the_pool_runs_jobs_left_on_workers_deques() {
+    let pool = work_stealing_pool(2);
+    let ran = Arc::new(AtomicUsize::new(0));
+
+    for _ in 0..8 {
+        let inner = Arc::clone(&pool);
+        let ran = Arc::clone(&ran);
+        pool.execute(move || {
+            for _ in 0..8 {
+                let ran = Arc::clone(&ran);
+                inner.execute(move || {
+                    ran.fetch_add(1, Ordering::SeqCst);
+                })
+                .unwrap();
+            }
+        })
+        .unwrap();
+    }
+
+    // Wait for the jobs queueing the others to finish, so the last handle is dropped here.
+    wait_until("the queueing jobs to finish", || Arc::strong_count(&pool) == 1);
+    drop(pool);
+    assert_eq!(ran.load(Ordering::SeqCst), 64);
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use std::{sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, mpsc, Arc}, thread, time::{Duration, Instant}};
+use server_rs::ThreadPool;
+
+use common::{hold_worker, holding_job, wait_for_queued, wait_until, TIMEOUT};
+
+#[test]
+fn cancelled_delayed_job_waiting_in_queue_is_skipped() {
+    let pool = ThreadPool::build(1).unwrap();
+
+    // Hold the only worker so the delayed job comes due but stays queued.
+    let held = hold_worker(&pool);
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    let job = {
+        let runs = Arc::clone(&runs);
+        pool.execute_after(Duration::from_millis(1), move || {
+            runs.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap()
+    };
+    wait_for_queued(&pool, 1);
+
+    job.cancel();
+    held.release();
+    pool.wait_idle();
+
+    assert_eq!(runs.load(Ordering::SeqCst), 0);
+    assert_eq!(pool.stats().cancelled, 1);
+}
+
+
+#[test]
+fn periodic_job_runs_once_every_period() {
+    let pool = ThreadPool::build(1).unwrap();
+    let period = Duration::from_millis(20);
+    let (ran_tx, ran_rx) = mpsc::channel();
+
+    let scheduled = Instant::now();
+    let job = pool.execute_every(period, move || {
+        let _ = ran_tx.send(Instant::now());
+    })
+    .unwrap();
+
+    // Each run comes due a whole period after the one before, counted from when it was scheduled.
+    for run in 1..=3 {
+        let ran = ran_rx.recv_timeout(TIMEOUT).unwrap();
+        assert!(ran.duration_since(scheduled) >= period * run, "run {run} came early");
+    }
+    job.cancel();
+}
+
+#[test]
+fn periodic_run_that_would_overlap_the_last_is_skipped() {
+    // With two workers, an overlapping run would have a worker free to start it.
+    let pool = ThreadPool::build(2).unwrap();
+    let period = Duration::from_millis(10);
+    let (held, first_run) = holding_job();
+    let running = Arc::new(AtomicBool::new(false));
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    let job = {
+        let (running, runs) = (Arc::clone(&running), Arc::clone(&runs));
+        let mut first_run = Some(first_run);
+        pool.execute_every(period, move || {
+            assert!(!running.swap(true, Ordering::SeqCst), "two runs at once");
+            runs.fetch_add(1, Ordering::SeqCst);
+            if let Some(first_run) = first_run.take() {
+                first_run();
+            }
+            running.store(false, Ordering::SeqCst);
+        })
+        .unwrap()
+    };
+
+    // Several runs come due while the first is held, and none of them start.
+    held.wait_started();
+    thread::sleep(period * 5);
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+    held.release();
+    wait_until("the job to run again", || runs.load(Ordering::SeqCst) >= 2);
+    job.cancel();
+    pool.wait_idle();
+    assert_eq!(pool.stats().panicked, 0);
+}
+
+#[test]
+fn cancelled_periodic_job_stops_running() {
+    let pool = ThreadPool::build(1).unwrap();
+    let period = Duration::from_millis(10);
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    let job = {
+        let runs = Arc::clone(&runs);
+        pool.execute_every(period, move || {
+            runs.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap()
+    };
+    wait_until("two runs", || runs.load(Ordering::SeqCst) >= 2);
+
+    job.cancel();
+    assert!(job.is_cancelled());
+    pool.wait_idle();
+    let stopped_at = runs.load(Ordering::SeqCst);
+    thread::sleep(period * 5);
+    assert_eq!(runs.load(Ordering::SeqCst), stopped_at);
+}
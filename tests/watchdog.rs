@@ -0,0 +1,86 @@
+use std::{sync::{atomic::{AtomicUsize, Ordering}, mpsc, Arc}, time::Duration};
+use server_rs::{logging::{Level, Logger, MemorySink}, ThreadPool};
+
+mod common;
+use common::{holding_job, wait_until, TIMEOUT};
+
+#[test]
+fn overdue_jobs_lists_only_the_jobs_past_their_deadline() {
+    let sink = Arc::new(MemorySink::new());
+    let pool = ThreadPool::builder().num_threads(2).logger(Logger::new(Arc::<MemorySink>::clone(&sink))).build().unwrap();
+
+    let (fast, job) = holding_job();
+    pool.execute_with_deadline("fast", Duration::from_secs(60), job).unwrap();
+    fast.wait_started();
+    let (slow, job) = holding_job();
+    pool.execute_with_deadline("slow", Duration::from_millis(10), job).unwrap();
+    slow.wait_started();
+
+    wait_until("the slow job to be overdue", || !pool.overdue_jobs().is_empty());
+    let overdue = pool.overdue_jobs();
+    assert_eq!(overdue.len(), 1);
+    assert_eq!(overdue[0].label, "slow");
+    assert_eq!(overdue[0].deadline, Duration::from_millis(10));
+    assert!(overdue[0].elapsed > overdue[0].deadline);
+
+    // The supervisor reports the overrun as well, naming the same worker.
+    wait_until("the overrun to be logged", || {
+        sink.events().iter().any(|event| event.message == "job has overrun its deadline")
+    });
+    let warning = sink.take().into_iter().find(|event| event.message == "job has overrun its deadline").unwrap();
+    assert_eq!(warning.level, Level::Warn);
+    assert_eq!(warning.field("job"), Some("slow"));
+    assert_eq!(warning.field("worker"), Some(overdue[0].worker.to_string().as_str()));
+
+    slow.release();
+    fast.release();
+    pool.wait_idle();
+    assert!(pool.overdue_jobs().is_empty());
+}
+
+#[test]
+fn stuck_worker_holds_up_the_queue_unless_replaced() {
+    let pool = ThreadPool::build(1).unwrap();
+    let (stuck, job) = holding_job();
+    pool.execute_with_deadline("stuck", Duration::from_millis(10), job).unwrap();
+    stuck.wait_started();
+
+    let (done_tx, done_rx) = mpsc::channel();
+    pool.execute(move || done_tx.send(()).unwrap()).unwrap();
+    assert!(done_rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+    stuck.release();
+    done_rx.recv_timeout(TIMEOUT).unwrap();
+}
+
+#[test]
+fn stuck_worker_is_replaced_and_retires_once_its_job_finishes() {
+    let stopped = Arc::new(AtomicUsize::new(0));
+    let pool = {
+        let stopped = Arc::clone(&stopped);
+        ThreadPool::builder()
+            .num_threads(1)
+            .replace_stuck_workers(true)
+            .on_thread_stop(move |_| {
+                stopped.fetch_add(1, Ordering::SeqCst);
+            })
+            .build()
+            .unwrap()
+    };
+    let (stuck, job) = holding_job();
+    pool.execute_with_deadline("stuck", Duration::from_millis(10), job).unwrap();
+    stuck.wait_started();
+
+    // The replacement picks up the queued job while the stuck worker is still busy.
+    let (done_tx, done_rx) = mpsc::channel();
+    pool.execute(move || done_tx.send(()).unwrap()).unwrap();
+    done_rx.recv_timeout(TIMEOUT).unwrap();
+    wait_until("the replacement to go idle", || pool.stats().running == 1);
+
+    assert_eq!(stopped.load(Ordering::SeqCst), 0);
+
+    stuck.release();
+    wait_until("the stuck worker to retire", || stopped.load(Ordering::SeqCst) == 1);
+    let stats = pool.stats();
+    assert_eq!((stats.running, stats.idle), (0, 1));
+}